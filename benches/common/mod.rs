@@ -0,0 +1,15 @@
+// no criterion here, just enough timing to compare two ways of doing something
+use std::time::{Duration, Instant};
+
+// runs f for about a second after a warmup and prints how long one go took
+pub fn bench(name: &str, mut f: impl FnMut()) {
+	const TARGET: Duration = Duration::from_secs(1);
+	for _ in 0..1000 { f(); }
+
+	let (start, mut iters) = (Instant::now(), 0u64);
+	while start.elapsed() < TARGET {
+		for _ in 0..100 { f(); }
+		iters += 100;
+	}
+	println!("{name:<40} {:>10.1?}/iter ({iters} iters)", start.elapsed() / iters as u32);
+}
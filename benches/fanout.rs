@@ -0,0 +1,69 @@
+// one channel, a lot of people in it. each subscriber awaiting recv() (what the event loop does now)
+// vs the old try_recv + a Notify next to the broadcast, which could only ever add wakeups
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+use sshc_russh_test::channel::Channel;
+use sshc_russh_test::event::Event;
+
+const SUBSCRIBERS: usize = 5000;
+const EVENTS:      usize = 200;
+
+struct Counts { wakeups: AtomicUsize, delivered: AtomicUsize }
+
+async fn run(with_notify: bool) -> (Duration, Counts) {
+	let channel = Arc::new(RwLock::new(Channel::new(1)));
+	let notify = Arc::new(Notify::new());
+	let counts = Arc::new(Counts { wakeups: AtomicUsize::new(0), delivered: AtomicUsize::new(0) });
+
+	let tasks = (0..SUBSCRIBERS).map(|_| {
+		let mut rx = Channel::subscribe(&channel).take_rx();
+		let (notify, counts) = (Arc::clone(&notify), Arc::clone(&counts));
+		tokio::spawn(async move {
+			let mut seen = 0;
+			while seen < EVENTS {
+				let got = match with_notify {
+					true => match rx.try_recv() {
+						Ok(_) => 1,
+						Err(TryRecvError::Empty) => { notify.notified().await; counts.wakeups.fetch_add(1, Ordering::Relaxed); 0 },
+						Err(TryRecvError::Lagged(n)) => { seen += n as usize; 0 },
+						Err(_) => break,
+					},
+					false => match rx.recv().await {
+						Ok(_) => { counts.wakeups.fetch_add(1, Ordering::Relaxed); 1 },
+						Err(RecvError::Lagged(n)) => { seen += n as usize; 0 },
+						Err(_) => break,
+					},
+				};
+				seen += got;
+				counts.delivered.fetch_add(got, Ordering::Relaxed);
+			}
+		})
+	}).collect::<Vec<_>>();
+	tokio::time::sleep(Duration::from_millis(200)).await; // everyone parked
+
+	let tx = channel.read().unwrap().tx.clone();
+	let name = Arc::<str>::from("bench");
+	let start = Instant::now();
+	for _ in 0..EVENTS {
+		let _ = tx.send(Event::Join(Arc::clone(&name)));
+		if with_notify { notify.notify_waiters(); }
+		tokio::time::sleep(Duration::from_millis(1)).await; // people dont all talk at once
+	}
+	for task in tasks { task.await.unwrap(); }
+	let took = start.elapsed();
+
+	(took, Arc::into_inner(counts).unwrap())
+}
+
+fn main() {
+	let rt = tokio::runtime::Builder::new_multi_thread().enable_time().build().unwrap();
+	for (name, with_notify) in [("fan-out, broadcast only", false), ("fan-out, broadcast + notify", true)] {
+		let (took, counts) = rt.block_on(run(with_notify));
+		println!("{name:<40} {took:>10.1?} for {SUBSCRIBERS} subscribers x {EVENTS} events, {} wakeups, {} delivered",
+			counts.wakeups.into_inner(), counts.delivered.into_inner());
+	}
+}
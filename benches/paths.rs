@@ -0,0 +1,44 @@
+// channel_from_path on a deep tree, walking it every time vs hitting the cache
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::hint::black_box;
+
+use sshc_russh_test::server::Server;
+use sshc_russh_test::channel::Channel;
+
+mod common;
+
+const DEPTH:    usize = 32;
+const SIBLINGS: usize = 16; // per level, so every step has a real map to look through
+
+fn deep_tree() -> (Server, PathBuf) {
+	let server = Server::default();
+	let mut parent = Arc::clone(&server.root_channel);
+	let mut path = PathBuf::from("/");
+	for depth in 0..DEPTH {
+		for sibling in 0..SIBLINGS {
+			let child = Arc::new(RwLock::new(Channel::new(server.new_channel_id())));
+			parent.write().unwrap().children.insert(Box::from(format!("c{sibling}")), child);
+		}
+		let next = Arc::clone(&parent.read().unwrap().children[&*format!("c{}", depth % SIBLINGS)]);
+		path.push(format!("c{}", depth % SIBLINGS));
+		parent = next;
+	}
+	(server, path)
+}
+
+fn main() {
+	let (mut server, path) = deep_tree();
+	let path: &Path = &path;
+
+	common::bench("channel_from_path, cold", || {
+		server.invalidate_paths();
+		black_box(server.channel_from_path(black_box(path)));
+	});
+	common::bench("channel_from_path, cached", || {
+		black_box(server.channel_from_path(black_box(path)));
+	});
+	common::bench("channel_from_path, made up (never cached)", || {
+		black_box(server.channel_from_path(black_box(Path::new("/c0/nope/nope"))));
+	});
+}
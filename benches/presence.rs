@@ -0,0 +1,55 @@
+// lots of people coming and going while something holds the server lock now and then.
+// presence used to live under that lock, "shared lock" does what that cost, "own lock" is what it is now
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use sshc_russh_test::server::{ServerSerializer, StateFormat, Presence};
+
+const SENDERS: usize = 8;
+const CHANGES: usize = 20_000; // per sender
+const HOLD:    Duration = Duration::from_micros(200); // serializing the state for a save, roughly
+
+fn run(shared_lock: bool) -> Duration {
+	// never saved, nothing gets written there
+	let state = Arc::new(ServerSerializer::new(&std::env::temp_dir().join("crussh-bench-nope"), StateFormat::Json, None));
+	let names = (0..SENDERS).map(|n| Arc::<str>::from(format!("user{n}"))).collect::<Vec<_>>();
+	for name in &names { state.online_mut().insert(Arc::clone(name), Presence::default()); }
+
+	let done = Arc::new(AtomicBool::new(false));
+	let writer = {
+		let (state, done) = (Arc::clone(&state), Arc::clone(&done));
+		std::thread::spawn(move || while !done.load(Ordering::Relaxed) {
+			let server = state.write();
+			let start = Instant::now();
+			while start.elapsed() < HOLD { std::hint::spin_loop(); }
+			drop(server);
+			std::thread::sleep(HOLD * 4);
+		})
+	};
+
+	let start = Instant::now();
+	let senders = names.into_iter().map(|name| {
+		let state = Arc::clone(&state);
+		std::thread::spawn(move || for i in 0..CHANGES {
+			let away = (i % 2 == 0).then(|| Arc::clone(&name));
+			match shared_lock {
+				true => { let _server = state.write(); state.set_away(&name, away); },
+				false => { state.set_away(&name, away); },
+			}
+		})
+	}).collect::<Vec<_>>();
+	for sender in senders { sender.join().unwrap(); }
+	let took = start.elapsed();
+
+	done.store(true, Ordering::Relaxed);
+	writer.join().unwrap();
+	took
+}
+
+fn main() {
+	for (name, shared_lock) in [("presence, shared lock", true), ("presence, own lock", false)] {
+		let took = run(shared_lock);
+		println!("{name:<40} {took:>10.1?} for {} changes ({:.1?}/change)", SENDERS * CHANGES, took / (SENDERS * CHANGES) as u32);
+	}
+}
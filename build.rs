@@ -0,0 +1,22 @@
+// the commit and russh version for :version, cargo doesnt hand either over by itself
+use std::process::Command;
+
+fn main() {
+	println!("cargo:rerun-if-changed=.git/HEAD");
+	println!("cargo:rerun-if-changed=.git/refs");
+	println!("cargo:rerun-if-changed=Cargo.lock");
+
+	// not a checkout, or no git around. :version just leaves it out
+	let commit = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output().ok()
+		.filter(|out| out.status.success())
+		.and_then(|out| String::from_utf8(out.stdout).ok());
+	if let Some(commit) = commit { println!("cargo:rustc-env=CRUSSH_COMMIT={}", commit.trim()); }
+
+	// whatever actually got resolved, not the range in Cargo.toml
+	let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+	let russh = lock.split("[[package]]")
+		.find(|package| package.contains("\nname = \"russh\"\n"))
+		.and_then(|package| package.lines().find_map(|line| line.strip_prefix("version = \"")))
+		.map(|version| version.trim_end_matches('"'));
+	if let Some(russh) = russh { println!("cargo:rustc-env=CRUSSH_RUSSH_VERSION={russh}"); }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::io::AsyncWriteExt;
+use log::{info, warn};
+
+// writes happen off to the side, so recording never holds up whoever is holding the user lock
+static LOGGER: OnceLock<UnboundedSender<String>> = OnceLock::new();
+
+pub fn start(path: PathBuf) {
+	let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+	if LOGGER.set(tx).is_err() { return; }
+
+	tokio::spawn(async move {
+		while let Some(line) = rx.recv().await {
+			let res = async {
+				tokio::fs::OpenOptions::new()
+					.create(true).append(true).open(&path).await?
+					.write_all(line.as_bytes()).await
+			}.await;
+
+			if let Err(e) = res { warn!("Error writing audit log {}: {e}", path.display()); }
+		}
+	});
+}
+
+// who did what to whom, one line per privileged action. 
+// always goes to the log, and to AUDIT_LOG too if that's set
+pub fn record(actor: &str, action: &str, target: &str) {
+	info!("audit: {actor} {action} {target}");
+
+	let Some(logger) = LOGGER.get() else { return; };
+	let _ = logger.send(format!("{} {actor} {action} {target}\n", 
+		chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+}
@@ -0,0 +1,69 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use rand::Rng;
+
+use crate::event::Event;
+use crate::CONFIG;
+
+// in-process for now, anything that can turn a message into a reply will do.
+// only messages starting with BOT_PREFIX get handed over, replies dont go back through bots
+pub trait Bot: Send + Sync {
+	// who replies show up as
+	fn name(&self) -> &str;
+	fn on_message(&self, channel: u64, event: &Event) -> Option<Event>;
+}
+
+// what BOTS can turn on
+#[derive(Clone, Copy)]
+pub enum BotKind { Dice }
+
+impl FromStr for BotKind {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"dice" => Ok(Self::Dice),
+			_ => Err(format!("unknown bot {s:?}, expected dice")),
+		}
+	}
+}
+
+impl BotKind {
+	pub fn build(self) -> Arc<dyn Bot> {
+		match self {
+			BotKind::Dice => Arc::new(Dice),
+		}
+	}
+}
+
+// !roll [NdM], 1d6 by default
+pub struct Dice;
+
+impl Bot for Dice {
+	fn name(&self) -> &str { "dice" }
+
+	fn on_message(&self, _: u64, event: &Event) -> Option<Event> {
+		const MAX_DICE: u32 = 100;
+		const MAX_SIDES: u32 = 1000;
+
+		let Event::Msg(_, uname, msg) = event else { return None; };
+		let mut args = msg.strip_prefix(CONFIG.bot_prefix.as_str())?.split_whitespace();
+		if args.next()? != "roll" { return None; }
+
+		let spec = args.next().unwrap_or("1d6");
+		let reply = match spec.split_once('d').map(|(n, m)| (if n.is_empty() { Ok(1) } else { n.parse() }, m.parse())) {
+			Some((Ok(n @ 1..=MAX_DICE), Ok(m @ 2..=MAX_SIDES))) => {
+				let mut rng = rand::thread_rng();
+				let rolls = (0..n).map(|_| rng.gen_range(1..=m)).collect::<Vec<u32>>();
+				match rolls.as_slice() {
+					[roll] => format!("{uname} rolled {spec}: {roll}"),
+					rolls  => format!("{uname} rolled {spec}: {} = {}", 
+						rolls.iter().map(u32::to_string).collect::<Vec<_>>().join(" + "),
+						rolls.iter().sum::<u32>()),
+				}
+			},
+			_ => format!("{uname}: usage: {}roll [NdM], up to {MAX_DICE}d{MAX_SIDES}", CONFIG.bot_prefix),
+		};
+
+		Some(Event::msg(Arc::from(self.name()), Arc::from(reply)))
+	}
+}
@@ -1,20 +1,22 @@
-use std::sync::{Arc, RwLock, Weak};
-use std::collections::HashMap;
-use tokio::sync::{Notify, broadcast::{self, Sender}};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::broadcast::{self, Receiver, Sender};
 use std::fmt;
 
-use crate::event::Event;
+use crate::event::{Event, MsgId, Quote};
+use crate::user::UserConfig;
 
-const BUFFER_SIZE: usize = 4;
+// typing goes through here too (at most one every few seconds per typer), so leave room for a busy channel
+const BUFFER_SIZE: usize = 64;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct Channel {
 	#[serde(skip)]
 	#[serde(default = "make_channel")]
 	pub tx: Sender<Event>,
-	#[serde(skip)]
-	#[serde(default = "make_notify")]
-	pub notify: Arc<Notify>,
+
+	#[serde(default)]
+	pub id: u64, // stays put when the channel moves around the tree, see Server::new_channel_id
 
 	#[serde(deserialize_with = "perms_sorted")]
 	pub perms: Vec<PermEntry>,
@@ -22,15 +24,102 @@ pub struct Channel {
 	pub children: HashMap<Box<str>, Arc<RwLock<Channel>>>,
 
 	// description: Option<Arc<str>>,
-	// history: Vec<Event>, // TODO: one way to handle it... maybe not the best
+	#[serde(default)]
+	pub history: Arc<Mutex<History>>, // shared with every subscriber so sending doesnt need the channel lock
+	#[serde(default)]
+	pub pins:    Vec<Message>,
+	#[serde(default)]
+	pub password: Option<u64>, // hashed like user passwords
+	#[serde(default)]
+	pub announce: bool, // only people who can manage it get to write
+	#[serde(default)]
+	pub inherit: bool, // no matching entry falls through to the parents perms
+
+	#[serde(skip)]
+	pub members: Members, // whos in here right now
+}
+
+// sessions per name, so someone on twice only counts once
+pub type Members = Arc<Mutex<HashMap<Arc<str>, usize>>>;
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct Message {
+	pub id:   MsgId,
+	pub from: Arc<str>,
+	pub msg:  Arc<str>,
+	pub time: u64,
+	#[serde(default)]
+	pub reply_to: Option<MsgId>,
+}
+
+// the last few messages, enough to point at them by id
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+pub struct History {
+	last_id:    MsgId,
+	pub recent: VecDeque<Message>,
+}
+
+impl History {
+	const LEN: usize = 100;
+
+	fn push(&mut self, from: &Arc<str>, msg: &Arc<str>, reply_to: Option<MsgId>) -> MsgId {
+		self.last_id += 1;
+		if self.recent.len() >= Self::LEN { self.recent.pop_front(); }
+		self.recent.push_back(Message {
+			id:   self.last_id,
+			from: Arc::clone(from),
+			msg:  Arc::clone(msg),
+			time: chrono::Utc::now().timestamp() as u64,
+			reply_to,
+		});
+		self.last_id
+	}
+
+	// what a reply to m quotes
+	pub fn quote(&self, m: &Message) -> Quote
+		{ Quote { id: m.id, msg: Arc::clone(&m.msg), thread: self.thread(m) } }
+
+	// who m was replying to, who that one was replying to and so on, until it runs out of history.
+	// one past what Event::Reply draws, so it knows to show theres more
+	fn thread(&self, m: &Message) -> Arc<[Arc<str>]> {
+		const MAX: usize = 4;
+		std::iter::successors(m.reply_to.and_then(|id| self.get(id)), |m| m.reply_to.and_then(|id| self.get(id)))
+			.take(MAX)
+			.map(|m| Arc::clone(&m.from))
+			.collect()
+	}
+
+	// how m gets shown again later, as the reply it was if what it replied to is still around
+	pub fn event(&self, m: &Message) -> Event {
+		let (from, msg) = (Arc::clone(&m.from), Arc::clone(&m.msg));
+		match m.reply_to.and_then(|id| self.get(id)) {
+			Some(to) => Event::Reply(m.id, from, Arc::clone(&to.from), msg, Some(self.quote(to))),
+			None     => Event::Msg(m.id, from, msg),
+		}
+	}
+
+	pub fn last_id(&self) -> MsgId
+		{ self.last_id }
+
+	pub fn get(&self, id: MsgId) -> Option<&Message> {
+		self.recent.binary_search_by_key(&id, |m| m.id).ok()
+			.map(|i| &self.recent[i])
+	}
+
+	pub fn get_mut(&mut self, id: MsgId) -> Option<&mut Message> {
+		self.recent.binary_search_by_key(&id, |m| m.id).ok()
+			.map(|i| &mut self.recent[i])
+	}
+
+	pub fn remove(&mut self, id: MsgId) -> Option<Message> {
+		self.recent.binary_search_by_key(&id, |m| m.id).ok()
+			.and_then(|i| self.recent.remove(i))
+	}
 }
 
 fn make_channel() -> Sender<Event> 
 	{ broadcast::channel(BUFFER_SIZE).0 }
 
-fn make_notify() -> Arc<Notify> 
-	{ Arc::new(Notify::new()) }
-
 // sort to perserve prescedence 
 // TODO: this might not actually be necessary
 fn perms_sorted<'de, D: serde::Deserializer<'de>>(d: D) 
@@ -41,66 +130,239 @@ fn perms_sorted<'de, D: serde::Deserializer<'de>>(d: D)
 	Ok(perms)
 }
 
-impl fmt::Display for Channel {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		fn draw_tree(
-			(name, channel): (&str, &Channel),
-			f: &mut fmt::Formatter,
-			level: usize, last: bool)
-		-> fmt::Result {
-			writeln!(f, "{level}{}{}{}\r", 
-				if level <= 1 { String::new() } else { "   ".repeat(level) },
-				if level < 1 { "" } else if last { "└─" } else { "├─" },
-				name)?;
-
-			channel.children.iter().enumerate().try_for_each(|(i, (n, key))|
-				draw_tree((n, &key.read().unwrap()), f, level + 1, 
-					channel.children.len() == i + 1))
-		}
+// see Channel::tree
+pub struct Tree<'a> {
+	channel: &'a Channel,
+	perms:   PermLevel, // what channel resolved to for them
+	depth:   Option<usize>,
+	user:    (&'a str, &'a UserConfig),
+}
+
+impl Tree<'_> {
+	// children they cant read are left out along with everything under them.
+	// indent is the bars and gaps of everything above, so each level lines up under its parent
+	fn draw_tree(&self,
+		(name, channel, perms): (&str, &Channel, PermLevel),
+		f: &mut fmt::Formatter,
+		level: usize, indent: &str, last: bool)
+	-> fmt::Result {
+		writeln!(f, "{indent}{}{name}{}{}\r", 
+			if level < 1 { "" } else if last { "└─" } else { "├─" },
+			if channel.announce { " (announce)" } else { "" },
+			if channel.inherit { " (inherits)" } else { "" })?;
+		if self.depth.is_some_and(|depth| level >= depth) { return Ok(()); }
+
+		let (user, config) = self.user;
+		let mut children = channel.children.iter()
+			.map(|(n, c)| {
+				let c = c.read().unwrap();
+				let perms = c.resolve(user, config, perms);
+				(n, c, perms)
+			})
+			.filter(|(_, c, perms)| c.effective(*perms, config).can_read())
+			.collect::<Vec<_>>();
+		children.sort_unstable_by(|a, b| a.0.cmp(b.0)); // hashmap order changes every time otherwise
 
-		draw_tree(("/", self), f, 0, true)
+		let indent = match level {
+			0 => String::new(),
+			_ => format!("{indent}{}", if last { "  " } else { "│ " }),
+		};
+		children.iter().enumerate().try_for_each(|(i, (n, c, perms))|
+			self.draw_tree((n, c, *perms), f, level + 1, &indent, children.len() == i + 1))
 	}
 }
 
+impl fmt::Display for Tree<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+		{ self.draw_tree(("/", self.channel, self.perms), f, 0, "", true) }
+}
+
 pub struct SubscribedChannel {
-	pub rx:     broadcast::Receiver<Event>,
+	pub rx:     Receiver<Event>,
 	pub tx:     Sender<Event>,
-	pub notify: Arc<Notify>, // we are slaves to the async
+	pub id:     u64,
+	pub history: Arc<Mutex<History>>,
+	pub effective_perms: PermLevel, // as of perms_at, go through perms() to keep it current
+	perms_at:   u64, // server generation they were worked out at, MAX for never again
+	member:     Option<(Arc<str>, Members)>,
+	lineage:    Vec<Weak<RwLock<Channel>>>, // for working perms out again, empty for bridges
 	channel:    Weak<RwLock<Channel>>,
 }
 
 impl Channel {
-	pub fn new() -> Self {
+	pub fn new(id: u64) -> Self {
 		Self {
 			tx:       broadcast::channel(BUFFER_SIZE).0,
-			notify:   Arc::new(Notify::new()),
+			id,
 			perms:    Vec::new(),
 			children: HashMap::new(),
+			history:  Arc::default(),
+			pins:     Vec::new(),
+			password: None,
+			announce: false,
+			inherit:  false,
+			members:  Members::default(),
 		}
 	}
 
+	// depth first over the whole subtree, parent stays locked while its children are visited
+	pub fn for_each_mut(channel: &Arc<RwLock<Self>>, f: &mut impl FnMut(&mut Self)) {
+		let mut channel = channel.write().unwrap();
+		f(&mut channel);
+		channel.children.values().for_each(|c| Self::for_each_mut(c, f));
+	}
+
+	// what this channel says about them before any overrides, first matching entry wins.
+	// parent is what the one above resolved to, only used when this one inherits
+	pub fn resolve(&self, name: &str, config: &UserConfig, parent: PermLevel) -> PermLevel {
+		self.perms.iter()
+			.find(|(r, _)| match r {
+				RestrictionKind::User(u) => &**u == name,
+				RestrictionKind::Role(r) => config.get_role(r).is_some(),
+				RestrictionKind::All     => true })
+			.map(|(_, p)| *p)
+			.unwrap_or(if self.inherit { parent } else { PermLevel::NONE })
+	}
+
+	// global managers and admins get to do anything and guests only get to look.
+	// announcement channels take write away from everyone that cant manage them
+	pub fn effective(&self, perms: PermLevel, config: &UserConfig) -> PermLevel {
+		let global = config.get_global_perms();
+		if global.can_manage() || global.can_admin() { return PermLevel::all(); }
+
+		if config.guest { return perms & PermLevel::READ; }
+		if self.announce && !perms.can_manage() { return perms - PermLevel::WRITE; }
+		perms
+	}
+
+	// lineage runs from / down to the channel in question, see Server::lineage.
+	// only one of them is locked at a time
+	pub fn perms_along(lineage: &[Arc<RwLock<Self>>], name: &str, config: &UserConfig) -> PermLevel {
+		let Some(channel) = lineage.last() else { return PermLevel::NONE; };
+		let perms = Self::resolve_along(lineage, name, config);
+		channel.read().unwrap().effective(perms, config)
+	}
+
+	// the same without effective, for carrying on down the tree from there
+	pub fn resolve_along(lineage: &[Arc<RwLock<Self>>], name: &str, config: &UserConfig) -> PermLevel {
+		lineage.iter()
+			.fold(PermLevel::NONE, |parent, c| c.read().unwrap().resolve(name, config, parent))
+	}
+
+	// what of the tree under it they get to see, depth counts down from here
+	pub fn tree<'a>(&'a self, perms: PermLevel, depth: Option<usize>, name: &'a str, config: &'a UserConfig) -> Tree<'a>
+		{ Tree { channel: self, perms, depth, user: (name, config) } }
+
+	// bridges and such speak for the server, so they get everything
 	pub fn subscribe(channel: &Arc<RwLock<Self>>) -> SubscribedChannel {
-		let (rx, tx, notify) = {
+		let (rx, tx, id, history) = {
 			let channel = channel.write().unwrap();
-			(channel.tx.subscribe(), channel.tx.clone(), channel.notify.clone())
+			(channel.tx.subscribe(), channel.tx.clone(), channel.id, channel.history.clone())
 		};
 
 		SubscribedChannel { 
-			rx, tx, notify,
+			rx, tx, id, history,
+			effective_perms: PermLevel::all(),
+			perms_at: u64::MAX,
+			member:   None,
+			lineage:  Vec::new(),
 			channel: Arc::downgrade(channel),
 		}
 	}
+
+	// counts towards whos here until its dropped. the last of lineage is the one subscribed to
+	pub fn subscribe_as(lineage: &[Arc<RwLock<Self>>], name: &Arc<str>, config: &UserConfig) -> SubscribedChannel {
+		let channel = lineage.last().expect("theres always at least /");
+		let mut sub = Self::subscribe(channel);
+		sub.perms_at = crate::SERVER.generation();
+		sub.effective_perms = Self::perms_along(lineage, name, config);
+		sub.lineage = lineage.iter().map(Arc::downgrade).collect();
+
+		let channel = channel.read().unwrap();
+
+		*channel.members.lock().unwrap().entry(Arc::clone(name)).or_default() += 1;
+		sub.member = Some((Arc::clone(name), Arc::clone(&channel.members)));
+		sub
+	}
 }
 
 impl SubscribedChannel {
-	pub fn send(&self, event: Event) -> Result<(), broadcast::error::SendError<Event>> {
-		self.tx.send(event)?;
-		self.notify.notify_waiters();
+	// how many different people are in here
+	pub fn here(&self) -> usize
+		{ self.member.as_ref().map_or(0, |(_, members)| members.lock().unwrap().len()) }
+
+	// for reading it from outside whatever holds this, like the user event loop does.
+	// events from now on still queue up in the one left behind, its just never read
+	pub fn take_rx(&mut self) -> Receiver<Event> {
+		let fresh = self.tx.subscribe();
+		std::mem::replace(&mut self.rx, fresh)
+	}
+
+	// stop counting towards whos here, without waiting to be dropped
+	pub fn part(&mut self) {
+		let Some((name, members)) = self.member.take() else { return; };
+		let mut members = members.lock().unwrap();
+		if let Some(count) = members.get_mut(&name) {
+			*count -= 1;
+			if *count == 0 { members.remove(&name); }
+		}
+	}
+
+	// worked out again whenever roles or perms might have changed since (they only change with a save)
+	pub fn perms(&mut self, name: &str, config: &UserConfig) -> PermLevel {
+		let generation = crate::SERVER.generation();
+		if self.perms_at == u64::MAX || self.perms_at == generation { return self.effective_perms; }
+
+		self.perms_at = generation;
+		// any of them gone means this one is too
+		self.effective_perms = self.lineage.iter().map(Weak::upgrade).collect::<Option<Vec<_>>>()
+			.map_or(PermLevel::NONE, |lineage| Channel::perms_along(&lineage, name, config));
+		self.effective_perms
+	}
+
+	pub fn send(&self, mut event: Event) -> Result<(), broadcast::error::SendError<Event>> {
+		self.stamp(&mut event);
+
+		let for_bots = match &event {
+			Event::Msg(_, _, msg) => msg.starts_with(crate::CONFIG.bot_prefix.as_str()),
+			_ => false,
+		};
+		if !for_bots { return self.broadcast(event); }
+
+		self.broadcast(event.clone())?;
+		for bot in crate::SERVER.bots() {
+			let Some(mut reply) = bot.on_message(self.id, &event) else { continue; };
+			self.stamp(&mut reply);
+			self.broadcast(reply)?;
+		}
+		Ok(())
+	}
+
+	// hand out an id and remember the message
+	fn stamp(&self, event: &mut Event) {
+		match event {
+			Event::Msg(id, from, msg) => *id = self.history.lock().unwrap().push(from, msg, None),
+			Event::Reply(id, from, _, msg, quote) => 
+				*id = self.history.lock().unwrap().push(from, msg, quote.as_ref().map(|q| q.id)),
+			_ => (),
+		}
+	}
+
+	fn broadcast(&self, event: Event) -> Result<(), broadcast::error::SendError<Event>> {
+		crate::chatlog::record(self.id, &event);
+		if matches!(event, Event::Msg(..) | Event::Reply(..)) 
+			{ crate::metrics::inc(&crate::metrics::MESSAGES); }
+		self.tx.send(event)?; // wakes everyone waiting in recv, theres nothing else to poke
 		Ok(())
 	}
 }
 
 
+impl Drop for SubscribedChannel {
+	fn drop(&mut self)
+	{ self.part(); }
+}
+
 impl std::ops::Deref for SubscribedChannel {
 	type Target = Weak<RwLock<Channel>>;
 
@@ -118,12 +380,174 @@ pub enum RestrictionKind {
 	All,
 }
 
+// who=perms, like all=read|write or role:mods=read|write|moderate, for DEFAULT_CHANNEL_PERMS
+pub struct PermSpec(pub PermEntry);
+
+impl std::str::FromStr for PermSpec {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (who, perms) = s.split_once('=').ok_or_else(|| format!("expected who=perms, got {s:?}"))?;
+		let who = match who.trim().split_once(':') {
+			None if who.trim() == "all" => RestrictionKind::All,
+			Some(("role", name)) if !name.is_empty() => RestrictionKind::Role(Arc::from(name)),
+			Some(("user", name)) if !name.is_empty() => RestrictionKind::User(Arc::from(name)),
+			_ => return Err(format!("expected all, role:<name> or user:<name>, got {who:?}")),
+		};
+		let perms = perms.split('|').map(str::trim)
+			.try_fold(PermLevel::NONE, |perms, name| match name {
+				"none" => Some(perms),
+				name => Some(perms | PermLevel::from_name(&name.to_uppercase())?),
+			})
+			.ok_or_else(|| format!("expected perms like read|write, got {perms:?}"))?;
+		Ok(Self((who, perms)))
+	}
+}
+
+// no ordering on purpose, comparing the bits says nothing about what they allow
 bitflags::bitflags! {
-	#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
+	#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
 	pub struct PermLevel: u8 {
 		const NONE   = 0;
 		const READ   = 1;
 		const WRITE  = 1 << 1;
 		const MANAGE = 1 << 2;
+		const MODERATE = 1 << 3; // keeping people in line, without touching the channel itself
+		const ADMIN    = 1 << 4; // the server itself, users and such. only means anything on roles
+	}
+}
+
+impl PermLevel {
+	pub fn can_read(self)   -> bool { self.contains(Self::READ) }
+	pub fn can_write(self)  -> bool { self.contains(Self::WRITE) }
+	pub fn can_manage(self) -> bool { self.contains(Self::MANAGE) }
+	pub fn can_moderate(self) -> bool { self.intersects(Self::MODERATE | Self::MANAGE) }
+	pub fn can_admin(self)  -> bool { self.contains(Self::ADMIN) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RW: PermLevel = PermLevel::READ.union(PermLevel::WRITE);
+
+	fn channel(perms: Vec<PermEntry>, inherit: bool) -> Arc<RwLock<Channel>> {
+		let mut channel = Channel::new(0);
+		(channel.perms, channel.inherit) = (perms, inherit);
+		Arc::new(RwLock::new(channel))
+	}
+
+	fn user(name: &str) -> RestrictionKind
+		{ RestrictionKind::User(Arc::from(name)) }
+
+	fn with_roles(roles: &[(&str, PermLevel)]) -> UserConfig
+		{ UserConfig { roles: roles.iter().map(|(n, p)| (Box::from(*n), *p)).collect(), ..Default::default() } }
+
+	#[test]
+	fn inherit_on_and_off() {
+		let root = channel(vec![(RestrictionKind::All, RW)], false);
+		let config = UserConfig::default();
+
+		let closed = channel(Vec::new(), false);
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root), closed], "bob", &config), PermLevel::NONE);
+
+		let open = channel(Vec::new(), true);
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root), open], "bob", &config), RW);
+
+		// only whoever it doesnt list falls through
+		let listed = channel(vec![(user("bob"), PermLevel::READ)], true);
+		let lineage = [root, listed];
+		assert_eq!(Channel::perms_along(&lineage, "bob", &config), PermLevel::READ);
+		assert_eq!(Channel::perms_along(&lineage, "alice", &config), RW);
+	}
+
+	#[test]
+	fn roles_and_order() {
+		let root = channel(vec![
+			(user("bob"), PermLevel::NONE),
+			(RestrictionKind::Role(Arc::from("mods")), RW | PermLevel::MODERATE),
+			(RestrictionKind::All, PermLevel::READ),
+		], false);
+		let mods = with_roles(&[("mods", RW)]);
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root)], "alice", &mods), RW | PermLevel::MODERATE);
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root)], "alice", &UserConfig::default()), PermLevel::READ);
+		// first match wins, so the user entry beats the role
+		assert_eq!(Channel::perms_along(&[root], "bob", &mods), PermLevel::NONE);
+	}
+
+	#[test]
+	fn user_override_deeper_down() {
+		let root = channel(vec![(RestrictionKind::All, PermLevel::READ)], false);
+		let a = channel(Vec::new(), true);
+		let b = channel(vec![(user("bob"), RW | PermLevel::MODERATE)], true);
+		let c = channel(Vec::new(), true);
+		let config = UserConfig::default();
+
+		let lineage = [root, a, b, c];
+		assert_eq!(Channel::perms_along(&lineage[..2], "bob", &config), PermLevel::READ);
+		assert_eq!(Channel::perms_along(&lineage[..3], "bob", &config), RW | PermLevel::MODERATE);
+		assert_eq!(Channel::perms_along(&lineage, "bob", &config), RW | PermLevel::MODERATE);
+		assert_eq!(Channel::perms_along(&lineage, "alice", &config), PermLevel::READ);
+
+		// and stops at the first that doesnt inherit
+		lineage[3].write().unwrap().inherit = false;
+		assert_eq!(Channel::perms_along(&lineage, "bob", &config), PermLevel::NONE);
+	}
+
+	#[test]
+	fn announce() {
+		let root = channel(vec![
+			(user("owner"), RW | PermLevel::MANAGE),
+			(RestrictionKind::All, RW),
+		], false);
+		root.write().unwrap().announce = true;
+		let config = UserConfig::default();
+
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root)], "bob", &config), PermLevel::READ);
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root)], "owner", &config), RW | PermLevel::MANAGE);
+		// global managers get everything anyway
+		let manager = with_roles(&[("staff", PermLevel::MANAGE)]);
+		assert_eq!(Channel::perms_along(&[root], "bob", &manager), PermLevel::all());
+	}
+
+	#[test]
+	fn tree_output() {
+		let add = |parent: &Arc<RwLock<Channel>>, name: &str, perms: Vec<PermEntry>, inherit: bool| {
+			let child = channel(perms, inherit);
+			parent.write().unwrap().children.insert(Box::from(name), Arc::clone(&child));
+			child
+		};
+		let root = channel(vec![(RestrictionKind::All, PermLevel::READ)], false);
+		let a = add(&root, "a", Vec::new(), true);
+		add(&a, "x", Vec::new(), true);
+		add(&a, "y", Vec::new(), true).write().unwrap().announce = true;
+		// hidden, and everything under it with it
+		let b = add(&root, "b", Vec::new(), false);
+		add(&b, "e", vec![(RestrictionKind::All, PermLevel::READ)], false);
+		let c = add(&root, "c", Vec::new(), true);
+		add(&c, "d", Vec::new(), true);
+
+		let config = UserConfig::default();
+		let root = root.read().unwrap();
+		let perms = root.resolve("bob", &config, PermLevel::NONE);
+		assert_eq!(root.tree(perms, None, "bob", &config).to_string(), concat!(
+			"/\r\n",
+			"├─a (inherits)\r\n",
+			"│ ├─x (inherits)\r\n",
+			"│ └─y (announce) (inherits)\r\n",
+			"└─c (inherits)\r\n",
+			"  └─d (inherits)\r\n",
+		));
+		assert_eq!(root.tree(perms, Some(1), "bob", &config).to_string(), "/\r\n├─a (inherits)\r\n└─c (inherits)\r\n");
+	}
+
+	#[test]
+	fn guests_only_read() {
+		let root = channel(vec![(RestrictionKind::All, RW | PermLevel::MANAGE)], false);
+		let guest = UserConfig { guest: true, ..Default::default() };
+		assert_eq!(Channel::perms_along(&[Arc::clone(&root)], "guest", &guest), PermLevel::READ);
+
+		// and whatever keeps them out still does
+		let closed = channel(Vec::new(), false);
+		assert_eq!(Channel::perms_along(&[root, closed], "guest", &guest), PermLevel::NONE);
 	}
 }
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::io::AsyncWriteExt;
+use tokio::fs::File;
+use log::warn;
+
+use crate::event::{Event, MsgId};
+
+enum Entry {
+	Line(String),
+	Delete(MsgId), // scrub every line about it
+}
+
+// everything goes through a single task so lines land in the order they were sent
+static LOGGER: OnceLock<UnboundedSender<(u64, Entry)>> = OnceLock::new();
+
+pub fn start(dir: PathBuf) {
+	let (tx, rx) = mpsc::unbounded_channel();
+	if LOGGER.set(tx).is_ok() { tokio::spawn(writer(dir, rx)); }
+}
+
+// no colours in here, these are meant to be grepped
+pub fn record(channel: u64, event: &Event) {
+	let Some(logger) = LOGGER.get() else { return; };
+
+	let line = match event {
+		Event::Msg(id, uname, msg)      => format!("#{id} {uname}: {msg}"),
+		Event::Reply(id, from, to, msg, _) => format!("#{id} {from} -> {to}: {msg}"),
+		Event::Edit(id, uname, msg)     => format!("#{id} {uname} (edited): {msg}"),
+		Event::Delete(id) => { let _ = logger.send((channel, Entry::Delete(*id))); return; },
+		_ => return,
+	};
+
+	// one line each, scrub goes by lines
+	let line = line.replace('\n', "\\n");
+	let line = format!("{} {line}\n", chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+	let _ = logger.send((channel, Entry::Line(line)));
+}
+
+// rewrites the whole file, deletes are rare enough for that to be fine
+async fn scrub(path: &std::path::Path, id: MsgId) -> std::io::Result<()> {
+	let log = match tokio::fs::read_to_string(path).await {
+		Ok(log) => log,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e),
+	};
+
+	let prefix = format!("#{id} ");
+	let kept = log.split_inclusive('\n')
+		.filter(|line| !line.split_once(' ').is_some_and(|(_, line)| line.starts_with(&prefix)))
+		.collect::<String>();
+	if kept.len() == log.len() { return Ok(()); }
+
+	let tmp = path.with_extension("log.tmp");
+	tokio::fs::write(&tmp, kept).await?;
+	tokio::fs::rename(&tmp, path).await
+}
+
+// keyed on channel ids, so logs follow channels around when they get moved
+async fn writer(dir: PathBuf, mut rx: mpsc::UnboundedReceiver<(u64, Entry)>) {
+	let mut files = HashMap::<u64, File>::new();
+
+	if let Err(e) = tokio::fs::create_dir_all(&dir).await 
+		{ warn!("Error creating log dir {}: {e}", dir.display()); }
+
+	while let Some((channel, entry)) = rx.recv().await {
+		let path = dir.join(format!("{channel}.log"));
+
+		let line = match entry {
+			Entry::Line(line) => line,
+			Entry::Delete(id) => {
+				files.remove(&channel); // gets reopened on the new file next time
+				if let Err(e) = scrub(&path, id).await 
+					{ warn!("Error removing message {id} from chat log {}: {e}", path.display()); }
+				continue;
+			},
+		};
+
+		let file = match files.get_mut(&channel) {
+			Some(file) => file,
+			None => match tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await {
+				Ok(file) => files.entry(channel).or_insert(file),
+				Err(e) => {
+					warn!("Error opening chat log {}: {e}", path.display());
+					continue;
+				},
+			},
+		};
+
+		if let Err(e) = file.write_all(line.as_bytes()).await {
+			warn!("Error writing chat log {}: {e}", path.display());
+			files.remove(&channel); // try reopening next time
+		}
+	}
+}
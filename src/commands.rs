@@ -1,17 +1,21 @@
 use std::sync::{Arc, RwLock};
-use std::mem::{self, ManuallyDrop};
-use std::path::Path;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
 use tokio::sync::MutexGuard;
 
 use russh::server::Session;
 use russh::{CryptoVec, ChannelId};
+use russh::keys::{PublicKey, HashAlg};
 
-use crate::user::{User, UserConfig};
+use crate::user::{User, UserConfig, UserState, Connection, Prompt, Clock, PASTE_PROMPT};
 use crate::Event;
 use crate::channel::{PermLevel, RestrictionKind};
-use crate::channel::Channel;
+use crate::channel::{Channel, SubscribedChannel, Message};
+use crate::event::MsgId;
+use crate::server::Server;
 use crate::event::colour::*;
-use crate::SERVER;
+use crate::{SERVER, CONFIG};
+use crate::audit;
 
 pub enum CommandError {
 	InvalidUtf8,
@@ -21,7 +25,8 @@ pub enum CommandError {
 	NotFound,
 	AlreadyExists,
 	Forbidden,
-	Unimplemented,
+	TooLong,
+	Busy,
 }
 
 use std::fmt;
@@ -35,258 +40,1547 @@ impl fmt::Display for CommandError {
 			Self::NotFound       => "ENFOUND: Not found",
 			Self::AlreadyExists  => "EEXIST: Already exists",
 			Self::Forbidden      => "EFRBD: Forbidden",
-			Self::Unimplemented  => "EUNIMP: Not implemented",
+			Self::TooLong        => "ETOOLONG: Too long",
+			Self::Busy           => "EBUSY: Close your other sessions first",
 		})
 	}
 }
 
-impl crate::ChatClient {
-	pub async fn command(
-		channel: ChannelId, 
-		session: &mut Session,
-		data: &[u8],
-		user: &mut MutexGuard<'_, ManuallyDrop<User>>)
-	-> Result<(), CommandError> {
-		macro_rules! data {
-			($data:expr) => { session.data(channel, CryptoVec::from_slice($data)).unwrap() }}
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Section { General, Channels, Account, Admin }
 
-		let cmd = std::str::from_utf8(data)
-			.map_err(|_| CommandError::InvalidUtf8)?
-			.split(' ').collect::<Vec<_>>();
+impl Section {
+	const ALL: [Section; 4] = [Section::General, Section::Channels, Section::Account, Section::Admin];
 
-		match cmd.as_slice() {
-			["help"] | ["h"] => {
-				const HELP: &[u8] = b"\
-					== Commands ==\r\n\
-					help, h         - show this message\r\n\
-					clear           - clear the terminal\r\n\
-					quit, q         - close the connection\r\n\
-					reply, r <name> - reply a message from <name>\r\n\
-					\r\n\
-					make-channel, mkch <name>       - create a new public channel\r\n\
-					make-priv-channel, mkchp <name> - create a new private channel\r\n\
-					remove-channel, rmch <name>     - remove a channel\r\n\
-					channel, ch <name>              - move to a channel\r\n\
-					all-users, lsa                  - list all online users\r\n\
-					whois <name>                    - get info on a user\r\n\
-					channel-perms, lsperm <name>    - list permissions for a channel\r\n\
-					\r\n\
-					passwd <pass>                   - change your password\r\n\
-					\r\n\
-					== Admin Commands ==\r\n\
-					useradd <name>                  - create a new user\r\n\
-					passwd-reset <name>             - reset a user's password\r\n";
-				user.info(HELP).await;
-			},
-			["quit"] | ["q"] => {
-				data!(b"\x1b[2K\r");
-				Self::close(session, channel, user).await;
-				return Ok(());
-			},
-			["clear"] => data!(b"\x1b[2J\x1b[H"),
-			["reply", args @ ..] | ["r", args @ ..] => {
-				let args = args.join(" "); 
-				let (name, msg) = args.split_once(' ')
-					.ok_or(CommandError::InvalidArgs)?;
-
-				SERVER.read().users
-					.contains_key(name).then_some(())
-					.ok_or(CommandError::NotFound)?;
-
-				user.channel.send(
-					Event::Reply(user.name.clone(), Arc::from(name), Arc::from(msg)))
-					.unwrap();
-			},
-			["useradd", name] => {
-				if user.config.lock().unwrap().get_global_perms() < PermLevel::MANAGE { 
-					Err(CommandError::Forbidden)?;
-				}
+	fn title(self) -> &'static str {
+		match self {
+			Section::General  => "Commands",
+			Section::Channels => "Channels",
+			Section::Account  => "Account",
+			Section::Admin    => "Admin Commands",
+		}
+	}
+}
 
-				let name = Arc::from(*name);
+// what each argument is, so completion knows what to offer
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+	Path, // a channel
+	User,
+	Word,
+	Text, // the rest of the line
+}
 
-				if SERVER.read().users.contains_key(&name) { Err(CommandError::AlreadyExists)?; }
+pub struct Ctx<'a, 'b> {
+	session:  &'a mut Session,
+	channel:  ChannelId,
+	pub user: &'a mut MutexGuard<'b, User>,
+}
 
-				let pass = UserConfig::gen_pass();
-				SERVER.write().users
-					.insert(name, Arc::new(std::sync::Mutex::new(UserConfig::new(&pass[..]))));
+impl Ctx<'_, '_> {
+	fn data(&mut self, data: &[u8]) {
+		crate::metrics::sent(data.len());
+		self.session.data(self.channel, CryptoVec::from_slice(data)).unwrap()
+	}
 
-				user.info(&pass[..]).await;
-			},
-			["passwd-reset", name] => {
-				if user.config.lock().unwrap().get_global_perms() < PermLevel::MANAGE 
-					{ Err(CommandError::Forbidden)?; }
+	fn close(&mut self)
+		{ crate::ChatClient::close(self.session, self.channel, self.user); }
+}
 
-				let pass = {
-					let users = &mut SERVER.write().users;
+// where the perms have to be held, channel meaning whichever one they're in
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope { Global, Channel }
 
-					let Some(user) = users.get_mut(&Arc::from(*name)) 
-						else { return Err(CommandError::NotFound); };
+type Handler = fn(&mut Ctx, &[&str]) -> Result<(), CommandError>;
 
-					let pass = UserConfig::gen_pass();
-					user.lock().unwrap().hash = UserConfig::hash(&pass[..]);
-					pass
-				};
+pub struct Command {
+	pub names: &'static [&'static str],
+	pub perms: PermLevel,
+	pub scope: Scope,
+	pub args:  &'static [Arg],
+	min_args:  usize,
+	run:       Handler,
+	usage:     &'static str,
+	about:     &'static str,
+	section:   Section,
+}
 
-				user.info(&pass[..]).await;
-			},
-			["passwd", pass] => {
-				user.config.lock().unwrap().hash = 
-					crate::user::UserConfig::hash(pass.as_bytes());
-			},
-			["make-priv-channel", n @ ..] | ["mkchp", n @ ..] => {
-				// TODO: create a priv channel
-				todo!()
-			},
-			["mkch", path] => {
-				let path = user.path.as_path().join(Path::new(path));
+impl Command {
+	fn takes(&self, n: usize) -> bool
+		{ n >= self.min_args && (n <= self.args.len() || self.args.last() == Some(&Arg::Text)) }
 
-				let channel = path.parent()
-					.and_then(|p| SERVER.read().channel_from_path(p))
-					.ok_or(CommandError::InvalidPath)?;
+	// channel perms change as they move around, so only global ones hide anything
+	pub fn listed(&self, global: PermLevel) -> bool
+		{ self.scope == Scope::Channel || global.contains(self.perms) }
 
-				let name = path.file_name()
-					.and_then(|n| n.to_str())
-					.ok_or(CommandError::InvalidPath)?;
+	fn allowed(&self, user: &mut User) -> bool {
+		match self.scope {
+			Scope::Global  => user.config.lock().unwrap().get_global_perms().contains(self.perms),
+			Scope::Channel => user.perms().contains(self.perms),
+		}
+	}
 
-				let channels = &mut channel.write().unwrap().children;
-				if channels.contains_key(name) { Err(CommandError::AlreadyExists)?; }
+	pub fn find(name: &str) -> Option<&'static Command>
+		{ COMMANDS.iter().find(|c| c.names.contains(&name)) }
+}
 
-				let mut channel = Channel::new();
+macro_rules! cmd {
+	($section:ident, [$($name:literal),+], $usage:literal, [$($arg:ident),*; $min:literal], $run:ident, $about:literal) =>
+		{ cmd!($section, Global(NONE), [$($name),+], $usage, [$($arg),*; $min], $run, $about) };
+	($section:ident, $scope:ident($perms:ident), [$($name:literal),+], $usage:literal, [$($arg:ident),*; $min:literal], $run:ident, $about:literal) =>
+		{ Command {
+			names: &[$($name),+], perms: PermLevel::$perms, scope: Scope::$scope, args: &[$(Arg::$arg),*], min_args: $min,
+			run: $run, usage: $usage, about: $about, section: Section::$section } };
+}
 
-				channel.perms.push((RestrictionKind::All, PermLevel::READ|PermLevel::WRITE));
-				channel.perms.push((RestrictionKind::User(user.name.clone()), PermLevel::READ|PermLevel::WRITE|PermLevel::MANAGE));
+// everything goes through here, dispatch, help and tab completion
+pub const COMMANDS: &[Command] = &[
+	cmd!(General,                  ["help", "h"],               "",                     [; 0],                 help,           "show this message"),
+	cmd!(General,                  ["clear"],                   "",                     [; 0],                 clear,          "clear the terminal"),
+	cmd!(General,                  ["version"],                 "",                     [; 0],                 version,        "show which build this server is running"),
+	cmd!(General,                  ["ping"],                    "",                     [; 0],                 ping,           "see how long a round trip to the server takes"),
+	cmd!(General,                  ["quit", "q"],               "",                     [; 0],                 quit,           "close the connection"),
+	cmd!(General, Channel(WRITE),  ["reply", "r"],              "<name>|#<id> <msg>",   [User, Text; 2],       reply,          "reply to someone, quoting them"),
+	cmd!(General, Channel(WRITE),  ["edit"],                    "<id> <msg>",           [Word, Text; 2],       edit,           "edit one of your recent messages"),
+	cmd!(General,                  ["delete", "del"],           "<id>",                 [Word; 1],             delete,         "delete one of your recent messages"),
+	cmd!(General, Channel(WRITE),  ["paste"],                   "",                     [; 0],                 paste,          "send several lines as one code block, :end to finish"),
+	cmd!(General,                  ["away"],                    "[message]",            [Text; 0],             away,           "mark yourself as away"),
+	cmd!(General,                  ["back"],                    "",                     [; 0],                 back,           "clear your away status"),
+	cmd!(General,                  ["all-users", "lsa"],        "",                     [; 0],                 all_users,      "list all online users"),
+	cmd!(General,                  ["whois"],                   "<name>",               [User; 1],             whois,          "get info on a user"),
 
-				channels.insert(Box::from(name), Arc::new(RwLock::new(channel)));
-			},
-			["remove-channel", path] | ["rmch", path] => {
-				let path = user.path.as_path().join(Path::new(path));
-
-				let channels = path.parent()
-					.and_then(|p| SERVER.read().channel_from_path(p))
-					.ok_or(CommandError::InvalidPath)?;
-
-				let name = path.file_name()
-					.and_then(|n| n.to_str())
-					.ok_or(CommandError::InvalidPath)?;
-
-				{
-					let config = user.config.lock().unwrap();
-					channels.read().unwrap().children.get(name)
-						.ok_or(CommandError::NotFound)?
-						.read().unwrap().perms.iter()
-						.find(|(r, _)| match r {
-							RestrictionKind::User(u) => *u == user.name,
-							RestrictionKind::Role(r) => config.get_role(r).is_some(),
-							RestrictionKind::All     => true })
-						.map(|_| ())
-						.or_else(|| (config.get_global_perms() > PermLevel::MANAGE).then_some(()))
-						.ok_or(CommandError::Forbidden)?;
-				}
+	cmd!(Channels,                 ["channel", "ch"],           "<name>|#<id>",         [Path; 1],             channel,        "move to a channel"),
+	cmd!(Channels,                 ["pwch"],                    "",                     [; 0],                 pwch,           "show the current channel"),
+	cmd!(Channels,                 ["fav"],                     "<name>",               [Path; 1],             fav,            "bookmark a channel"),
+	cmd!(Channels,                 ["unfav"],                   "<name>",               [Path; 1],             unfav,          "drop a bookmark"),
+	cmd!(Channels,                 ["favs"],                    "",                     [; 0],                 favs,           "list your bookmarks"),
+	cmd!(Channels,                 ["find"],                    "<name>",               [Word; 1],             find,           "find channels by name"),
+	cmd!(Channels,                 ["lsch"],                    "[name] [depth]",       [Path, Word; 0],       lsch,           "show the channels under one"),
+	cmd!(Channels,                 ["make-channel", "mkch"],    "[-p] <name>",          [Path, Word; 1],       make_channel,   "create a new public channel"),
+	cmd!(Channels,                 ["remove-channel", "rmch"],  "[-y] <name>",          [Path, Word; 1],       remove_channel, "remove a channel"),
+	cmd!(Channels,                 ["channel-perms", "lsperm"], "<name>",               [Path; 1],             lsperm,         "list permissions for a channel"),
+	cmd!(Channels,                 ["invite"],                  "<name> <user> [read]", [Path, User, Word; 2], invite,         "let someone into a channel"),
+	cmd!(Channels,                 ["kick-from"],               "<name> <user>",        [Path, User; 2],       kick_from,      "take back someone's access to a channel"),
+	cmd!(Channels,                 ["mod"],                     "<name> <user>",        [Path, User; 2],       make_mod,       "let someone moderate a channel"),
+	cmd!(Channels,                 ["unmod"],                   "<name> <user>",        [Path, User; 2],       unmod,          "stop someone moderating a channel"),
+	cmd!(Channels,                 ["announce"],                "<name>",               [Path; 1],             announce,       "only let managers talk in a channel"),
+	cmd!(Channels,                 ["unannounce"],              "<name>",               [Path; 1],             unannounce,     "let everyone talk again"),
+	cmd!(Channels,                 ["inherit"],                 "<name>",               [Path; 1],             inherit,        "anyone it doesnt list gets the parents perms"),
+	cmd!(Channels,                 ["noinherit"],               "<name>",               [Path; 1],             noinherit,      "only what it lists counts"),
+	cmd!(Channels,                 ["setpass"],                 "<name> <pass>",        [Path, Word; 2],       set_password,   "make a channel ask for a password"),
+	cmd!(Channels,                 ["clearpass"],               "<name>",               [Path; 1],             set_password,   "stop a channel asking for a password"),
+	cmd!(Channels, Channel(READ),  ["search"],                  "<text>",               [Text; 1],             search,         "look through the recent messages here"),
+	cmd!(Channels,                 ["export"],                  "[name]",               [Path; 0],             export,         "dump the recent messages as plain text"),
+	cmd!(Channels,                 ["pins"],                    "",                     [; 0],                 pins,           "list pinned messages"),
+	cmd!(Channels, Channel(WRITE), ["pin"],                     "<id>",                 [Word; 1],             pin,            "pin a message in this channel"),
+	cmd!(Channels, Channel(WRITE), ["unpin"],                   "<id>",                 [Word; 1],             unpin,          "unpin a message"),
 
-				channels.write().unwrap()
-					.children.remove(name).unwrap();
-			},
-			["channel", path] | ["ch", path] => {
-				let path = user.path.as_path().join(Path::new(path));
+	cmd!(Account,                  ["set"],                     "[<setting> <value>]",  [Word, Word; 0],       set,            "show or change your settings"),
+	cmd!(Account,                  ["clock"],                   "12|24",                [Word; 1],             clock,          "same as set clock"),
+	cmd!(Account,                  ["tz"],                      "<iana name>",          [Word; 1],             tz,             "same as set tz"),
+	cmd!(Account,                  ["bio"],                     "[text|clear]",         [Text; 0],             bio,            "show or change what whois says about you"),
+	cmd!(Account,                  ["prompt"],                  "[format|default]",     [Text; 0],             prompt,         "show or change your prompt, %c channel %u name %n here"),
+	cmd!(Account,                  ["passwd"],                  "<pass>",               [Word; 1],             passwd,         "change your password"),
+	cmd!(Account,                  ["rename-user"],             "<name>",               [Word; 1],             rename_user,    "change your username"),
+	cmd!(Account,                  ["unregister"],              "",                     [; 0],                 unregister,     "delete your account"),
+	cmd!(Account,                  ["register"],                "<name> <pass>",        [Word, Word; 2],       register,       "create a new account (if enabled)"),
+	#[cfg(feature = "totp")]
+	cmd!(Account,                  ["2fa-enable"],              "",                     [; 0],                 totp_enable,    "ask for a code from an app after your password"),
+	#[cfg(feature = "totp")]
+	cmd!(Account,                  ["2fa-disable"],             "",                     [; 0],                 totp_disable,   "stop asking for one"),
+	cmd!(Account,                  ["key-add"],                 "<key>",                [Text; 1],             key_add,        "let an ssh public key log you in"),
+	cmd!(Account,                  ["keys"],                    "",                     [; 0],                 keys,           "list the keys that can log you in"),
+	cmd!(Account,                  ["key-rm"],                  "<n>",                  [Word; 1],             key_rm,         "stop a key logging you in"),
+
+	cmd!(Admin, Global(ADMIN),     ["useradd"],                 "<name>",               [Word; 1],             useradd,        "create a new user"),
+	cmd!(Admin, Global(ADMIN),     ["passwd-reset"],            "<name>",               [User; 1],             passwd_reset,   "reset a user's password"),
+	cmd!(Admin, Global(ADMIN),     ["deluser"],                 "[-y] <name>",          [User, Word; 1],       deluser,        "delete a user"),
+];
+
+const KEYS: &[(&str, &str)] = &[
+	("tab",    "complete commands, channels and names"),
+	("ctrl-c", "disconnect, or clear the line (see set)"),
+	("ctrl-d", "disconnect on an empty line, delete forward otherwise"),
+	("ctrl-l", "clear the screen"),
+];
+
+// only shows what they can actually use
+fn help_text(perms: PermLevel) -> String {
+	let usage = |c: &Command| match c.usage {
+		"" => c.names.join(", "),
+		args => format!("{} {args}", c.names.join(", ")),
+	};
 
-				let channel = SERVER.read().channel_from_path(&path)
-					.ok_or(CommandError::InvalidPath)?;
+	let shown = COMMANDS.iter().filter(|c| c.listed(perms));
+	let width = shown.clone().map(|c| usage(c).len())
+		.chain(KEYS.iter().map(|(key, _)| key.len()))
+		.max().unwrap_or(0);
 
-				user.path = path;
+	let line = |usage: &str, names: usize, about: &str| format!("{BOLD}{}{RESET}{:pad$} {BRIGHT_BLACK}- {about}{RESET}\r\n",
+		&usage[..names], &usage[names..], pad = width - names);
 
-				mem::drop(mem::replace(&mut user.channel, Channel::subscribe(&channel)));
+	let mut help = Section::ALL.iter()
+		.filter(|&&s| shown.clone().any(|c| c.section == s))
+		.fold(String::new(), |mut help, &section| {
+			help += &format!("{BOLD}== {} =={RESET}\r\n", section.title());
+			for c in shown.clone().filter(|c| c.section == section)
+				{ help += &line(&usage(c), c.names.join(", ").len(), c.about); }
+			help + "\r\n"
+		});
+
+	help += &format!("{BOLD}== Keys =={RESET}\r\n");
+	for (key, about) in KEYS { help += &line(key, key.len(), about); }
+	help
+}
+
+// drops the account along with any perms naming it, handing back the sessions that still need kicking
+fn remove_user(name: &Arc<str>) -> Result<Vec<Arc<Connection>>, CommandError> {
+	let mut server = SERVER.write();
+
+	let is_admin = server.users.get(name)
+		.ok_or(CommandError::NotFound)?
+		.lock().unwrap().get_global_perms().can_admin();
+
+	// dont lock everyone out
+	if is_admin && server.users.values()
+		.filter(|c| c.lock().unwrap().get_global_perms().can_admin())
+		.count() <= 1 
+		{ Err(CommandError::Forbidden)?; }
+
+	server.users.remove(name);
+	Channel::for_each_mut(&server.root_channel, &mut |channel|
+		channel.perms.retain(|(r, _)| !matches!(r, RestrictionKind::User(u) if u == name)));
+
+	Ok(SERVER.online_mut().remove(name).map(|p| p.conns).unwrap_or_default())
+}
+
+fn kick(conns: Vec<Arc<Connection>>) {
+	for conn in conns {
+		conn.data(CryptoVec::from_slice(b"\x1b[2K\rAccount deleted.\r\n"));
+		conn.close();
+	}
+}
+
+fn fmt_pins(pins: &[Message]) -> String {
+	pins.iter().fold(String::from("== Pinned ==\r\n"), |s, pin| 
+		s + &format!("{BRIGHT_BLACK}{}{RESET} {BOLD}{}{RESET}: {}\r\n", pin.id, pin.from, pin.msg))
+}
+
+// timestamps are shown in whoever's looking's own time
+fn time_prefs(user: &User) -> (chrono_tz::Tz, Clock) {
+	let config = user.config.lock().unwrap();
+	(config.tz(), config.clock())
+}
+
+fn local_time(time: u64, tz: chrono_tz::Tz) -> chrono::DateTime<chrono_tz::Tz>
+	{ chrono::DateTime::from_timestamp(time as i64, 0).unwrap_or_default().with_timezone(&tz) }
+
+fn parse_id(id: &str) -> Result<MsgId, CommandError>
+	{ id.trim_start_matches('#').parse().map_err(|_| CommandError::InvalidArgs) }
+
+// sending anything counts as being back, and whoever the message was aimed at
+// (replied to or @mentioned) gets their away message shown to the sender
+pub fn after_send(user: &mut User, msg: &str, reply_to: Option<&str>) {
+	SERVER.set_away(&user.name, None);
+
+	let mut targets = msg.split_whitespace()
+		.filter_map(|word| word.strip_prefix('@'))
+		.map(|name| name.trim_end_matches(['.', ',', '!', '?', ':', ';']))
+		.chain(reply_to)
+		.collect::<Vec<_>>();
+	targets.sort_unstable();
+	targets.dedup();
+
+	let notice = targets.into_iter()
+		.filter_map(|name| SERVER.away(name).map(|away| match away.is_empty() {
+			true  => format!("{BOLD}{name}{RESET} is away\r\n"),
+			false => format!("{BOLD}{name}{RESET} is away: {away}\r\n"),
+		}))
+		.collect::<String>();
+	if !notice.is_empty() { user.info(notice.as_bytes()); }
+}
+
+
+const MAX_PASTE: usize = 4096;
+
+fn paste(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	user.conn.data(CryptoVec::from(format!(
+		"\x1b[2K\r{BRIGHT_BLACK}pasting, :end on a line of its own sends it and ctrl-c gives up{RESET}\r\n")));
+	user.state = UserState::Paste(Vec::new());
+	user.redraw();
+	Ok(())
+}
+
+// a byte at a time, since a real paste comes in as one chunk with \r (or \r\n) between the lines
+pub fn paste_data(user: &mut User, data: &[u8]) {
+	let mut echo = Vec::new();
+	let mut bytes = data.iter().copied().peekable();
+	while let Some(b) = bytes.next() {
+		match b {
+			3 | 4 => {
+				user.state = UserState::Normal;
+				user.buf_clear();
+				user.info(b"Never mind.\r\n");
+				return;
 			},
-			["pwch"] => {
-				// SAFETY: info doesnt even get close to modyfying user path. 
-				// SAFETY: &mut is needed so it can send data. 
-				// SAFETY: we're just sidestepping the borrow checker to let it read the path directly
-				let path = user.path.as_os_str().as_encoded_bytes() as *const _;
-				user.info(unsafe { &*path }).await;
+			b'\r' | b'\n' => {
+				if b == b'\r' { bytes.next_if_eq(&b'\n'); }
+				let line = String::from_utf8_lossy(&user.buffer).into_owned();
+				user.buf_clear();
+
+				let UserState::Paste(lines) = &mut user.state else { return; };
+				if line.trim() == ":end" {
+					let lines = mem::take(lines);
+					user.state = UserState::Normal;
+					user.conn.data(CryptoVec::from(echo));
+					if let Err(e) = send_paste(user, &lines) { user.info(e.to_string().as_bytes()); }
+					return;
+				}
+
+				lines.push(line);
+				if lines.iter().map(|l| l.len() + 1).sum::<usize>() > MAX_PASTE {
+					user.state = UserState::Normal;
+					user.info(CommandError::TooLong.to_string().as_bytes());
+					return;
+				}
+				echo.extend(format!("\r\n{PASTE_PROMPT}").as_bytes());
 			},
-			["lsch"] => {
-				let thing = SERVER.read().channel_from_path(&user.path)
-					.ok_or(CommandError::InvalidPath)?
-					.read().unwrap().to_string();
-				user.info(thing.as_bytes()).await;
+			127 => if let Some(end) = std::str::from_utf8(&user.buffer).ok().and_then(|s| s.char_indices().last()) {
+				user.buffer.truncate(end.0);
+				user.cursor = user.buffer.len();
+				echo.extend(b"\x1b[D\x1b[P");
 			},
-			["lsch", path] => {
-				let path = user.path.as_path().join(Path::new(path));
-				let thing = SERVER.read().channel_from_path(&path)
-					.ok_or(CommandError::InvalidPath)?
-					.read().unwrap().to_string();
-				user.info(thing.as_bytes()).await;
+			// arrows and such, theres no moving around in here
+			27 => if bytes.next_if_eq(&b'[').is_some() { bytes.by_ref().find(|b| (b'@'..=b'~').contains(b)); },
+			b'\t' => {
+				user.buffer.extend(b"    ");
+				echo.extend(b"    ");
 			},
-			["users"] | ["ls"] => {
-				// TODO: list all users in current channel
-				Err(CommandError::Unimplemented)?;
+			b if b.is_ascii_control() => (),
+			b => {
+				user.buffer.push(b);
+				echo.push(b);
 			},
-			["all-users"] | ["lsa"] => {
-				let userlist = SERVER.read().online_users
-					.iter().fold(String::new(), |s, k| s + k + "\r\n");
+		}
+		user.cursor = user.buffer.len();
+	}
+	user.conn.data(CryptoVec::from(echo));
+}
 
-				user.info(userlist.as_bytes()).await;
-			},
-			["whois", name] => {
-				use chrono::{Utc, TimeZone};
-
-				let name = Arc::from(*name);
-				let buf = {
-					let server = SERVER.read();
-					let user = server.users.get(&name)
-						.ok_or(CommandError::NotFound)?
-						.lock().unwrap();
-
-					let fmt_time = |time: u64| {
-						let time = Utc.timestamp_opt(time as i64, 0).unwrap();
-						format!("{} ({BOLD}{}{RESET} ago)", 
-							time.format(&format!("{BOLD}%H:%M{RESET} %B %d, %Y")),
-							humantime::format_duration(std::time::Duration::from_secs(
-								Utc::now().signed_duration_since(time)
-									.to_std().unwrap().as_secs())))
-					};
-
-					let mut buf: Vec<u8> = match server.online_users.contains(&name) {
-						false => Vec::from(b"online: false\r\n"),
-						true  => format!("online: {}\r\n", fmt_time(user.online_time)).into_bytes(),
-					};
-
-					// current-channel: (/path)
-					// buf.extend(b"current-channel: ");
-					// buf.extend(user.path.as_os_str().as_encoded_bytes());
-					// buf.extend(b"\r\n");
-					
-					if user.last_login != 0 {
-						buf.extend(format!("last-online: {}\r\n", fmt_time(user.last_login)).as_bytes());
-					}
-
-					if !user.roles.is_empty() {
-						buf.extend(b"roles:\r\n");
-						user.roles.iter().enumerate().for_each(|(index, (name, level))|
-							buf.extend(format!("{} {:?} - {:?}\r\n",
-							if index == user.roles.len() - 1  { "└──" } else { "├──" },
-							name, level).as_bytes()));
-					}
-
-					buf
-				};
-				user.info(&buf).await;
+fn send_paste(user: &mut User, lines: &[String]) -> Result<(), CommandError> {
+	if lines.iter().all(|l| l.trim().is_empty()) {
+		user.info(b"Never mind.\r\n");
+		return Ok(());
+	}
+	// perms might have moved since they started
+	if !user.perms().can_write() { Err(CommandError::Forbidden)?; }
+
+	let msg = Arc::<str>::from(format!("```\n{}\n```", lines.join("\n")));
+	user.channel.send(Event::msg(user.name.clone(), Arc::clone(&msg))).unwrap();
+	user.redraw();
+	after_send(user, &msg, None);
+	Ok(())
+}
+
+fn help(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let perms = ctx.user.config.lock().unwrap().get_global_perms();
+	ctx.user.info(help_text(perms).as_bytes());
+	Ok(())
+}
+
+fn clear(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	ctx.data(b"\x1b[2J\x1b[H");
+	Ok(())
+}
+
+// the commit and russh version come from build.rs
+fn version(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let mut info = format!("crussh {}", env!("CARGO_PKG_VERSION"));
+	if let Some(commit) = option_env!("CRUSSH_COMMIT") { info += &format!(" ({commit})"); }
+	info += "\r\n";
+	if let Some(russh) = option_env!("CRUSSH_RUSSH_VERSION") { info += &format!("russh {russh}\r\n"); }
+
+	ctx.user.info(info.as_bytes());
+	Ok(())
+}
+
+// asks the terminal where its cursor is, it answers by itself so theres nothing to wait on
+// but the connection. see pong
+fn ping(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	ctx.user.ping = Some(std::time::Instant::now());
+	ctx.user.conn.data(CryptoVec::from_slice(b"\x1b[6n"));
+	Ok(())
+}
+
+// true if data was the answer to a ping, ESC [ row ; col R
+pub fn pong(user: &mut User, data: &[u8]) -> bool {
+	let is_report = data.strip_prefix(b"\x1b[").and_then(|d| d.strip_suffix(b"R"))
+		.is_some_and(|pos| pos.iter().all(|b| b.is_ascii_digit() || *b == b';'));
+	let Some(sent) = user.ping.take_if(|_| is_report) else { return false; };
+
+	user.clear_shown();
+	user.info(format!("pong, {}ms\r\n", sent.elapsed().as_millis()).as_bytes());
+	true
+}
+
+fn quit(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	ctx.close();
+	Ok(())
+}
+
+fn reply(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let (target, msg) = (args[0], args[1..].join(" "));
+	let msg = user.config.lock().unwrap().expand(&msg).into_owned();
+
+	// #<id> quotes that message, a name quotes whatever they said last
+	let (name, quote) = match target.strip_prefix('#') {
+		Some(id) => {
+			let history = user.channel.history.lock().unwrap();
+			history.get(parse_id(id)?)
+				.map(|m| (Arc::clone(&m.from), Some(history.quote(m))))
+				.ok_or(CommandError::NotFound)?
+		},
+		None => {
+			SERVER.read().users
+				.contains_key(target).then_some(())
+				.ok_or(CommandError::NotFound)?;
+
+			let history = user.channel.history.lock().unwrap();
+			let quote = history.recent.iter().rev()
+				.find(|m| &*m.from == target)
+				.map(|m| history.quote(m));
+			(Arc::from(target), quote)
+		},
+	};
+
+	user.channel.send(Event::reply(user.name.clone(), Arc::clone(&name), Arc::from(msg.as_str()), quote))
+		.unwrap();
+	after_send(user, &msg, Some(&name));
+	Ok(())
+}
+
+fn set(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let off = |value: &str| match value {
+		"on"  => Ok(false),
+		"off" => Ok(true),
+		_ => Err(CommandError::InvalidArgs),
+	};
+	let user = &mut *ctx.user;
+	match args {
+		[] => {
+			let default = |set: bool| if set { "" } else { " (default)" };
+			let on = |off: bool| if off { "off" } else { "on" };
+			let (ctrl_c, clock, tz, no_emoji, no_markdown) = {
+				let config = user.config.lock().unwrap();
+				(config.ctrl_c, config.clock, config.tz.clone(), config.no_emoji, config.no_markdown)
+			};
+			let msg = format!("ctrl-c: {}{}\r\nclock: {}{}\r\ntz: {}{}\r\nemoji: {}\r\nmarkdown: {}\r\n",
+				ctrl_c.unwrap_or(CONFIG.ctrl_c), default(ctrl_c.is_some()),
+				clock.unwrap_or(CONFIG.clock), default(clock.is_some()),
+				tz.as_deref().unwrap_or("UTC"), default(tz.is_some()),
+				on(no_emoji), on(no_markdown));
+			user.info(msg.as_bytes());
+		},
+		["ctrl-c", value] => {
+			let value = value.parse().map_err(|_| CommandError::InvalidArgs)?;
+			user.config.lock().unwrap().ctrl_c = Some(value);
+			SERVER.write().changed(); // so it gets saved
+		},
+		["clock", value] => {
+			let value = value.parse().map_err(|_| CommandError::InvalidArgs)?;
+			user.config.lock().unwrap().clock = Some(value);
+			SERVER.write().changed();
+		},
+		["tz", value] => {
+			let tz = value.parse::<chrono_tz::Tz>().map_err(|_| CommandError::InvalidArgs)?;
+			user.config.lock().unwrap().tz = Some(Box::from(tz.name()));
+			SERVER.write().changed();
+		},
+		["emoji", value] => {
+			user.config.lock().unwrap().no_emoji = off(value)?;
+			SERVER.write().changed();
+		},
+		["markdown", value] => {
+			user.config.lock().unwrap().no_markdown = off(value)?;
+			SERVER.write().changed();
+		},
+		_ => Err(CommandError::InvalidArgs)?,
+	}
+	Ok(())
+}
+
+// shorthands, so they show up in help
+fn clock(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> { set(ctx, &["clock", args[0]]) }
+fn tz(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError>    { set(ctx, &["tz", args[0]]) }
+
+fn bio(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	const MAX_BIO: usize = 160; // chars
+	let user = &mut *ctx.user;
+	match args {
+		[] => {
+			let bio = user.config.lock().unwrap().bio.clone();
+			let msg = match bio {
+				Some(bio) => format!("bio: {bio}\r\n"),
+				None => String::from("No bio yet.\r\n"),
+			};
+			user.info(msg.as_bytes());
+			return Ok(());
+		},
+		["clear"] => user.config.lock().unwrap().bio = None,
+		_ => {
+			// same as search and export get, so no escapes end up in someone elses whois
+			let bio = plain(&args.join(" "));
+			let bio = bio.trim();
+			if bio.is_empty() { Err(CommandError::InvalidArgs)?; }
+			if bio.chars().count() > MAX_BIO { Err(CommandError::TooLong)?; }
+			user.config.lock().unwrap().bio = Some(Box::from(bio));
+		},
+	}
+	SERVER.write().changed(); // so it gets saved
+	Ok(())
+}
+
+fn prompt(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	match args {
+		[] => {
+			let prompt = user.config.lock().unwrap().prompt.clone();
+			let msg = match prompt {
+				Some(prompt) => format!("prompt: {prompt}\r\n"),
+				None => format!("prompt: {} (default)\r\n", CONFIG.prompt),
+			};
+			user.info(msg.as_bytes());
+			return Ok(());
+		},
+		["default"] => user.config.lock().unwrap().prompt = None,
+		_ => {
+			let format = args.join(" ");
+			if let Err(e) = format.parse::<Prompt>() {
+				user.info(format!("{RED}{BOLD}EBADA: {e}{RESET}\r\n").as_bytes());
+				return Ok(());
+			}
+			user.config.lock().unwrap().prompt = Some(Box::from(format));
+		},
+	}
+	SERVER.write().changed(); // so it gets saved
+	Ok(())
+}
+
+fn away(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	SERVER.set_away(&ctx.user.name, Some(Arc::from(args.join(" ").trim())));
+	ctx.user.info(b"You are now away, :back or send a message to come back.\r\n");
+	Ok(())
+}
+
+fn back(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	match SERVER.set_away(&ctx.user.name, None) {
+		true  => ctx.user.info(b"Welcome back.\r\n"),
+		false => ctx.user.info(b"You weren't away.\r\n"),
+	}
+	Ok(())
+}
+
+fn useradd(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	if !Server::valid_name(args[0]) { Err(CommandError::InvalidArgs)?; }
+	let name = Arc::from(args[0]);
+
+	if SERVER.read().users.contains_key(&name) { Err(CommandError::AlreadyExists)?; }
+
+	let pass = UserConfig::gen_pass();
+	SERVER.write().users
+		.insert(Arc::clone(&name), Arc::new(std::sync::Mutex::new(UserConfig::new(&pass[..]))));
+	audit::record(&ctx.user.name, "useradd", &name);
+
+	ctx.user.info(&pass[..]);
+	Ok(())
+}
+
+fn passwd_reset(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let pass = {
+		let users = &mut SERVER.write().users;
+
+		let Some(user) = users.get_mut(&Arc::from(args[0]))
+			else { return Err(CommandError::NotFound); };
+
+		let pass = UserConfig::gen_pass();
+		user.lock().unwrap().hash = UserConfig::hash(&pass[..]);
+		pass
+	};
+	audit::record(&ctx.user.name, "passwd-reset", args[0]);
+
+	ctx.user.info(&pass[..]);
+	Ok(())
+}
+
+fn register(_: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	if !CONFIG.allow_registration { Err(CommandError::Forbidden)?; }
+
+	let (name, pass) = (args[0], args[1]);
+	if !Server::valid_name(name) { Err(CommandError::InvalidArgs)?; }
+	let name = Arc::from(name);
+
+	let mut server = SERVER.write();
+	if server.users.contains_key(&name) { Err(CommandError::AlreadyExists)?; }
+
+	server.users.insert(name,
+		Arc::new(std::sync::Mutex::new(UserConfig::new(pass.as_bytes()))));
+	Ok(())
+}
+
+fn deluser(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (yes, args) = take_yes(args);
+	let [name] = args[..] else { Err(CommandError::InvalidArgs)? };
+
+	let name = Arc::<str>::from(name);
+	if name == ctx.user.name { Err(CommandError::InvalidArgs)?; } // use unregister
+	if !SERVER.read().users.contains_key(&name) { Err(CommandError::NotFound)?; }
+
+	confirm(ctx.user, yes, format!("delete user {name}?"), move |user| {
+		kick(remove_user(&name)?);
+		audit::record(&user.name, "deluser", &name);
+		Ok(())
+	})
+}
+
+fn unregister(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let conns = remove_user(&ctx.user.name)?;
+	kick(conns.into_iter().filter(|c| !Arc::ptr_eq(c, &ctx.user.conn)).collect());
+
+	ctx.close();
+	Ok(())
+}
+
+fn passwd(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	ctx.user.config.lock().unwrap().hash = UserConfig::hash(args[0].as_bytes());
+	SERVER.write().changed(); // so it gets saved
+	Ok(())
+}
+
+// only kept once they show their app makes the right codes for it, so a bad scan cant lock them out
+#[cfg(feature = "totp")]
+fn totp_enable(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	if !CONFIG.totp { Err(CommandError::Forbidden)?; }
+	let user = &mut *ctx.user;
+	if user.config.lock().unwrap().totp.is_some() { Err(CommandError::AlreadyExists)?; }
+
+	let secret = crate::totp::gen_secret();
+	user.conn.data(CryptoVec::from(format!("Add this to your authenticator app:\r\n{}\r\n",
+		crate::totp::uri(&user.name, &secret))));
+
+	user.prompt(String::from("code: "), false, Box::new(move |user, code| {
+		let code = std::str::from_utf8(code).map_err(|_| CommandError::InvalidUtf8)?;
+		if !crate::totp::verify(&secret, code) { Err(CommandError::Forbidden)?; }
+
+		user.config.lock().unwrap().totp = Some(secret.into());
+		SERVER.write().changed(); // so it gets saved
+		audit::record(&user.name, "2fa-enable", "");
+		user.info(b"Two factor auth is on, you'll get asked for a code after your password.\r\n");
+		Ok(())
+	}));
+	Ok(())
+}
+
+// wants a current code too, so a session left open isnt enough to turn it off
+#[cfg(feature = "totp")]
+fn totp_disable(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let Some(secret) = user.config.lock().unwrap().totp.clone() else { Err(CommandError::NotFound)? };
+
+	user.prompt(String::from("code: "), false, Box::new(move |user, code| {
+		let code = std::str::from_utf8(code).map_err(|_| CommandError::InvalidUtf8)?;
+		if !crate::totp::verify(&secret, code) { Err(CommandError::Forbidden)?; }
+
+		user.config.lock().unwrap().totp = None;
+		SERVER.write().changed(); // so it gets saved
+		audit::record(&user.name, "2fa-disable", "");
+		user.info(b"Two factor auth is off.\r\n");
+		Ok(())
+	}));
+	Ok(())
+}
+
+// the whole line from a .pub file, comment and all
+fn key_add(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	const MAX_KEYS: usize = 16;
+	let key = PublicKey::from_openssh(&args.join(" ")).map_err(|_| CommandError::InvalidArgs)?;
+	let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+	{
+		let keys = &mut ctx.user.config.lock().unwrap().keys;
+		let parsed = || keys.iter().filter_map(|k| PublicKey::from_openssh(k).ok());
+		if parsed().any(|k| k.key_data() == key.key_data()) { Err(CommandError::AlreadyExists)?; }
+		if keys.len() >= MAX_KEYS { Err(CommandError::TooLong)?; }
+		keys.push(key.to_openssh().map_err(|_| CommandError::InvalidArgs)?.into());
+	}
+	SERVER.write().changed(); // so it gets saved
+	audit::record(&ctx.user.name, "key-add", &fingerprint);
+	Ok(())
+}
+
+fn keys(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let keys = ctx.user.config.lock().unwrap().keys.clone();
+	if keys.is_empty() {
+		ctx.user.info(b"No keys yet, add one with :key-add.\r\n");
+		return Ok(());
+	}
+	let list = keys.iter().enumerate().filter_map(|(i, k)| Some((i, PublicKey::from_openssh(k).ok()?)))
+		.map(|(i, k)| format!("{}: {} {} {}\r\n", i + 1, k.algorithm(), k.fingerprint(HashAlg::Sha256), k.comment()))
+		.collect::<String>();
+	ctx.user.info(list.as_bytes());
+	Ok(())
+}
+
+// by the number :keys shows
+fn key_rm(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let n = args[0].parse::<usize>().map_err(|_| CommandError::InvalidArgs)?;
+	let key = {
+		let keys = &mut ctx.user.config.lock().unwrap().keys;
+		if n == 0 || n > keys.len() { Err(CommandError::NotFound)?; }
+		keys.remove(n - 1)
+	};
+	SERVER.write().changed(); // so it gets saved
+	let fingerprint = PublicKey::from_openssh(&key).map(|k| k.fingerprint(HashAlg::Sha256).to_string()).unwrap_or_default();
+	audit::record(&ctx.user.name, "key-rm", &fingerprint);
+	Ok(())
+}
+
+fn rename_user(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	if !Server::valid_name(args[0]) { Err(CommandError::InvalidArgs)?; }
+	let new = Arc::<str>::from(args[0]);
+
+	let mut server = SERVER.write();
+	if server.users.contains_key(&new) { Err(CommandError::AlreadyExists)?; }
+
+	// every other session would keep the old name and go offline under it, leaving a ghost behind
+	let mut online = SERVER.online_mut();
+	if online.get(&ctx.user.name).is_some_and(|presence| presence.sessions() > 1) { Err(CommandError::Busy)?; }
+
+	let conf = server.users.remove(&ctx.user.name).ok_or(CommandError::NotFound)?;
+	server.users.insert(Arc::clone(&new), conf);
+
+	if let Some(presence) = online.remove(&ctx.user.name)
+		{ online.insert(Arc::clone(&new), presence); }
+	drop(online);
+
+	// keep whatever the old name had access to
+	let old = mem::replace(&mut ctx.user.name, Arc::clone(&new));
+	Channel::for_each_mut(&server.root_channel, &mut |channel| {
+		channel.perms.iter_mut()
+			.filter(|(r, _)| matches!(r, RestrictionKind::User(u) if *u == old))
+			.for_each(|(r, _)| *r = RestrictionKind::User(Arc::clone(&new)));
+		channel.perms.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+	});
+	drop(server);
+
+	audit::record(&old, "rename-user", &new);
+	Ok(())
+}
+
+fn make_channel(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let (parents, args) = take_flag(args, &["-p"]);
+	let [path] = args[..] else { Err(CommandError::InvalidArgs)? };
+	let path = user.path.as_path().join(Path::new(path));
+
+	// no . or .. in the middle, those would just end up as channel names
+	let names = path.components().skip(1)
+		.map(|c| match c {
+			Component::Normal(name) => name.to_str().ok_or(CommandError::InvalidPath),
+			_ => Err(CommandError::InvalidPath),
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+	if names.is_empty() { Err(CommandError::AlreadyExists)?; }
+
+	// whatever of it is already there, the rest gets made
+	let lineage = SERVER.read().lineage(&path);
+	let missing = &names[lineage.len() - 1..];
+	match missing.len() {
+		0 => Err(CommandError::AlreadyExists)?,
+		1 => (),
+		_ if parents => (),
+		_ => Err(CommandError::InvalidPath)?,
+	}
+
+	let perms = Channel::perms_along(&lineage, &user.name, &user.config.lock().unwrap());
+	if !perms.can_write() && !perms.can_manage() { Err(CommandError::Forbidden)?; }
+
+	let mut made = PathBuf::from("/");
+	made.extend(&names[..lineage.len() - 1]);
+	let mut info = String::new();
+	{
+		let mut parent = Arc::clone(lineage.last().unwrap());
+		for name in missing {
+			let child = {
+				let mut parent = parent.write().unwrap();
+				if parent.children.contains_key(*name) { Err(CommandError::AlreadyExists)?; }
+				let child = Arc::new(RwLock::new(new_child(&parent, &user.name)));
+				parent.children.insert(Box::from(*name), Arc::clone(&child));
+				child
+			};
+			parent = child;
+
+			made.push(name);
+			info += &format!("created {}\r\n", made.display());
+		}
+	}
+	SERVER.write().invalidate_paths(); // mut, so the new channels get saved
+
+	if parents { user.info(info.as_bytes()); }
+	Ok(())
+}
+
+// the creator manages it and everyone else gets DEFAULT_CHANNEL_PERMS.
+// under an inheriting one they get whatever the parent gives them instead
+fn new_child(parent: &Channel, creator: &Arc<str>) -> Channel {
+	let mut channel = Channel::new(SERVER.read().new_channel_id());
+	let creator = RestrictionKind::User(Arc::clone(creator));
+	match parent.inherit {
+		true  => channel.inherit = true,
+		false => channel.perms.extend(CONFIG.channel_perms.iter().filter(|(who, _)| *who != creator).cloned()),
+	}
+	channel.perms.push((creator, PermLevel::READ|PermLevel::WRITE|PermLevel::MANAGE));
+
+	// in precedence order, the same as they'd be sorted on load
+	channel.perms.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+	channel
+}
+
+fn remove_channel(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let (yes, args) = take_yes(args);
+	let [path] = args[..] else { Err(CommandError::InvalidArgs)? };
+	let path = user.path.as_path().join(Path::new(path));
+
+	let channels = path.parent()
+		.and_then(|p| SERVER.read().channel_at(p))
+		.ok_or(CommandError::InvalidPath)?;
+
+	let name = path.file_name()
+		.and_then(|n| n.to_str())
+		.map(String::from)
+		.ok_or(CommandError::InvalidPath)?;
+
+	let target = channels.read().unwrap().children.get(&*name)
+		.cloned().ok_or(CommandError::NotFound)?;
+	if !can_remove(&SERVER.read(), &path, &user.name, &user.config.lock().unwrap())
+		{ Err(CommandError::Forbidden)?; }
+
+	let question = match descendants(&target.read().unwrap()) {
+		0 => format!("delete {}?", path.display()),
+		1 => format!("delete {} and 1 child?", path.display()),
+		n => format!("delete {} and {n} children?", path.display()),
+	};
+
+	confirm(user, yes, question, move |user| {
+		let removed = channels.write().unwrap()
+			.children.remove(&*name).ok_or(CommandError::NotFound)?;
+		SERVER.write().invalidate_paths(); // mut, so the removal gets saved
+
+		// anyone still in there (or further down) gets moved out by their event loop
+		Channel::for_each_mut(&removed, &mut |c| { let _ = c.tx.send(Event::Removed); });
+
+		audit::record(&user.name, "rmch", &path.to_string_lossy());
+		Ok(())
+	})
+}
+
+// managers of the channel itself, or global ones. perms_at would go by the closest parent otherwise
+fn can_remove(server: &Server, path: &Path, name: &str, config: &UserConfig) -> bool
+	{ server.channel_at(path).is_some() && server.perms_at(path, name, config).can_manage() }
+
+fn descendants(channel: &Channel) -> usize
+	{ channel.children.values().map(|c| 1 + descendants(&c.read().unwrap())).sum() }
+
+// -y or --yes anywhere skips the confirmation
+fn take_yes<'a>(args: &[&'a str]) -> (bool, Vec<&'a str>)
+	{ take_flag(args, &["-y", "--yes"]) }
+
+// whether any of flags is in there, and everything else
+fn take_flag<'a>(args: &[&'a str], flags: &[&str]) -> (bool, Vec<&'a str>) {
+	let rest = args.iter().copied().filter(|a| !flags.contains(a)).collect::<Vec<_>>();
+	(rest.len() != args.len(), rest)
+}
+
+fn confirm(user: &mut User, yes: bool, question: String, then: impl FnOnce(&mut User) -> Result<(), CommandError> + Send + 'static) 
+-> Result<(), CommandError> {
+	if yes { return then(user); }
+
+	user.prompt(format!("{question} [y/N] "), false, Box::new(|user, answer| match answer.trim_ascii() {
+		b"y" | b"Y" | b"yes" => then(user),
+		_ => {
+			user.info(b"Never mind.\r\n");
+			Ok(())
+		},
+	}));
+	Ok(())
+}
+
+fn channel(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let (path, channel) = match args[0].strip_prefix('#') {
+		Some(id) => SERVER.read()
+			.channel_from_id(id.parse().map_err(|_| CommandError::InvalidArgs)?)
+			.ok_or(CommandError::NotFound)?,
+		None => {
+			let path = user.path.as_path().join(Path::new(args[0]));
+			let channel = SERVER.read().channel_from_path(&path)
+				.ok_or(CommandError::InvalidPath)?;
+			(path, channel)
+		},
+	};
+
+	let lineage = SERVER.read().lineage(&path);
+	let sub = Channel::subscribe_as(&lineage, &user.name, &user.config.lock().unwrap());
+	if !sub.effective_perms.can_read() { Err(CommandError::Forbidden)?; }
+
+	// managers dont need the password to their own channel
+	if channel.read().unwrap().password.is_some() && !sub.effective_perms.can_manage() {
+		let prompt = format!("password for {}: ", path.display());
+		user.prompt(prompt, true, Box::new(move |user, pass| unlock_channel(user, path, &channel, pass)));
+		return Ok(());
+	}
+
+	enter(user, path, &channel, sub);
+	Ok(())
+}
+
+pub fn enter(user: &mut User, path: PathBuf, channel: &Arc<RwLock<Channel>>, sub: SubscribedChannel) {
+	const MAX_REPLAY: usize = 20;
+
+	// only the channels in question hear about it
+	user.mark_seen();
+	let _ = user.channel.send(Event::Leave(user.name.clone()));
+	user.path = path;
+
+	mem::drop(mem::replace(&mut user.channel, sub));
+	let _ = user.channel.send(Event::Join(user.name.clone()));
+
+	// been here before, catch them up on what they missed
+	let (seen, markdown) = {
+		let config = user.config.lock().unwrap();
+		(config.last_seen.get(&user.channel.id).copied(), !config.no_markdown)
+	};
+	if let Some(seen) = seen {
+		let history = user.channel.history.lock().unwrap();
+		let missed = history.recent.iter()
+			.filter(|m| m.id > seen)
+			.map(|m| format!("{}\r\n", history.event(m).styled(markdown)))
+			.collect::<Vec<_>>();
+		mem::drop(history);
+
+		if !missed.is_empty() {
+			let mut out = match missed.len() {
+				1 => String::from("1 new message since you were last here:\r\n"),
+				n if n > MAX_REPLAY => format!("{n} new messages since you were last here, the last {MAX_REPLAY}:\r\n"),
+				n => format!("{n} new messages since you were last here:\r\n"),
+			};
+			out += &missed[missed.len().saturating_sub(MAX_REPLAY)..].concat();
+			user.conn.data(CryptoVec::from(out));
+		}
+	}
+
+	let mut info = format!("{BOLD}{}{RESET} {BRIGHT_BLACK}({} here){RESET}\r\n", user.path.display(), user.channel.here());
+	let (pins, announce) = {
+		let channel = channel.read().unwrap();
+		(channel.pins.clone(), channel.announce)
+	};
+	if announce { info += &format!("{BRIGHT_BLACK}announcements only, just managers can talk here{RESET}\r\n"); }
+	if !pins.is_empty() { info += &fmt_pins(&pins); }
+	user.info(info.as_bytes());
+}
+
+// whatever they typed at the password prompt
+fn unlock_channel(user: &mut User, path: PathBuf, channel: &Arc<RwLock<Channel>>, pass: &[u8]) -> Result<(), CommandError> {
+	if channel.read().unwrap().password != Some(UserConfig::hash(pass)) {
+		log::warn!("{} got the password for {} wrong", user.name, path.display());
+		Err(CommandError::Forbidden)?;
+	}
+
+	// perms might have moved while they were typing
+	let lineage = SERVER.read().lineage(&path);
+	let sub = Channel::subscribe_as(&lineage, &user.name, &user.config.lock().unwrap());
+	if !sub.effective_perms.can_read() { Err(CommandError::Forbidden)?; }
+
+	enter(user, path, channel, sub);
+	Ok(())
+}
+
+fn set_password(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (path, channel) = managed_channel(ctx.user, args[0])?;
+	channel.write().unwrap().password = args.get(1).map(|pass| UserConfig::hash(pass.as_bytes()));
+
+	SERVER.write().changed(); // so it gets saved
+	audit::record(&ctx.user.name, if args.len() > 1 { "setpass" } else { "clearpass" }, &path.to_string_lossy());
+	Ok(())
+}
+
+fn fav(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let path = ctx.user.path.as_path().join(Path::new(args[0]));
+	SERVER.read().channel_at(&path).ok_or(CommandError::InvalidPath)?;
+
+	let path = Box::<str>::from(path.to_string_lossy());
+	{
+		let favorites = &mut ctx.user.config.lock().unwrap().favorites;
+		if favorites.contains(&path) { Err(CommandError::AlreadyExists)?; }
+		favorites.push(path);
+	}
+	SERVER.write().changed(); // so it gets saved
+	Ok(())
+}
+
+fn unfav(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let path = ctx.user.path.as_path().join(Path::new(args[0]));
+	let path = path.to_string_lossy();
+	{
+		let favorites = &mut ctx.user.config.lock().unwrap().favorites;
+		let len = favorites.len();
+		favorites.retain(|f| **f != *path);
+		if favorites.len() == len { Err(CommandError::NotFound)?; }
+	}
+	SERVER.write().changed(); // so it gets saved
+	Ok(())
+}
+
+fn favs(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let favorites = ctx.user.config.lock().unwrap().favorites.clone();
+	match favorites.is_empty() {
+		true  => ctx.user.info(b"No favorites yet, add some with :fav.\r\n"),
+		false => ctx.user.info(favorites.iter().map(|f| format!("{f}\r\n")).collect::<String>().as_bytes()),
+	}
+	Ok(())
+}
+
+fn pins(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let channel = ctx.user.channel.upgrade().ok_or(CommandError::NotFound)?;
+	let pins = channel.read().unwrap().pins.clone();
+	match pins.is_empty() {
+		true  => ctx.user.info(b"Nothing pinned here.\r\n"),
+		false => ctx.user.info(fmt_pins(&pins).as_bytes()),
+	}
+	Ok(())
+}
+
+fn pin(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let id = parse_id(args[0])?;
+	let channel = user.channel.upgrade().ok_or(CommandError::NotFound)?;
+
+	let msg = user.channel.history.lock().unwrap()
+		.get(id).cloned().ok_or(CommandError::NotFound)?;
+	{
+		let pins = &mut channel.write().unwrap().pins;
+		if pins.iter().any(|p| p.id == id) { Err(CommandError::AlreadyExists)?; }
+		pins.push(msg);
+	}
+	SERVER.write().changed(); // so the pin gets saved
+	user.info(format!("Pinned {id}.\r\n").as_bytes());
+	Ok(())
+}
+
+fn unpin(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let id = parse_id(args[0])?;
+	let channel = user.channel.upgrade().ok_or(CommandError::NotFound)?;
+
+	{
+		let pins = &mut channel.write().unwrap().pins;
+		let len = pins.len();
+		pins.retain(|p| p.id != id);
+		if pins.len() == len { Err(CommandError::NotFound)?; }
+	}
+	SERVER.write().changed(); // so it stays unpinned
+	user.info(format!("Unpinned {id}.\r\n").as_bytes());
+	Ok(())
+}
+
+fn edit(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let id = parse_id(args[0])?;
+	let msg = Arc::<str>::from(user.config.lock().unwrap().expand(args[1..].join(" ").trim()));
+	if msg.is_empty() { Err(CommandError::InvalidArgs)?; }
+
+	let channel = user.channel.upgrade().ok_or(CommandError::NotFound)?;
+	let manager = user.perms().can_manage(); // rewording others words is for managers, moderators only get to delete
+
+	// same window as delete
+	let author = {
+		let mut history = user.channel.history.lock().unwrap();
+		let old = history.get_mut(id).ok_or(CommandError::NotFound)?;
+
+		let age = (chrono::Utc::now().timestamp() as u64).saturating_sub(old.time);
+		let in_window = CONFIG.edit_window.is_none_or(|window| age <= window.as_secs());
+		if !manager && (old.from != user.name || !in_window) { Err(CommandError::Forbidden)?; }
+
+		old.msg = Arc::clone(&msg);
+		Arc::clone(&old.from)
+	};
+
+	// keep the pinned copy in sync
+	if let Some(pin) = channel.write().unwrap().pins.iter_mut().find(|p| p.id == id)
+		{ pin.msg = Arc::clone(&msg); }
+	SERVER.write().changed(); // so the saved history has it too
+
+	user.channel.send(Event::Edit(id, author, msg)).unwrap();
+	Ok(())
+}
+
+// authors get the edit window, moderators can clear out anything still in the history
+fn delete(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let id = parse_id(args[0])?;
+	let channel = user.channel.upgrade().ok_or(CommandError::NotFound)?;
+	let moderator = user.perms().can_moderate();
+
+	{
+		let mut history = user.channel.history.lock().unwrap();
+		let msg = history.get(id).ok_or(CommandError::NotFound)?;
+
+		let age = (chrono::Utc::now().timestamp() as u64).saturating_sub(msg.time);
+		let in_window = CONFIG.edit_window.is_none_or(|window| age <= window.as_secs());
+		if !moderator && (msg.from != user.name || !in_window) { Err(CommandError::Forbidden)?; }
+
+		history.remove(id);
+	}
+
+	channel.write().unwrap().pins.retain(|p| p.id != id);
+	SERVER.write().changed(); // so its gone from the saved history too
+
+	user.channel.send(Event::Delete(id)).unwrap();
+	Ok(())
+}
+
+fn pwch(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	// SAFETY: info doesnt even get close to modyfying user path.
+	// SAFETY: &mut is needed so it can send data.
+	// SAFETY: we're just sidestepping the borrow checker to let it read the path directly
+	let path = user.path.as_os_str().as_encoded_bytes() as *const _;
+	user.info(unsafe { &*path });
+	Ok(())
+}
+
+fn lsch(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let (path, depth) = match args {
+		[]            => ("", None),
+		[path]        => (*path, None),
+		[path, depth] => (*path, Some(depth.parse().map_err(|_| CommandError::InvalidArgs)?)),
+		_ => Err(CommandError::InvalidArgs)?,
+	};
+	let path = user.path.as_path().join(Path::new(path));
+
+	let lineage = SERVER.read().lineage(&path);
+	let tree = {
+		let config = user.config.lock().unwrap();
+		let perms = Channel::resolve_along(&lineage, &user.name, &config);
+		let channel = lineage.last().unwrap().read().unwrap();
+		if !channel.effective(perms, &config).can_read() { Err(CommandError::Forbidden)?; }
+		channel.tree(perms, depth, &user.name, &config).to_string()
+	};
+	user.info(tree.as_bytes());
+	Ok(())
+}
+
+fn all_users(ctx: &mut Ctx, _: &[&str]) -> Result<(), CommandError> {
+	let userlist = SERVER.online().iter()
+		.fold(String::new(), |s, (name, presence)| match presence.away.as_deref() {
+			None     => s + name + "\r\n",
+			Some("") => s + name + " (away)\r\n",
+			Some(away) => s + name + " (away: " + away + ")\r\n",
+		});
+
+	ctx.user.info(userlist.as_bytes());
+	Ok(())
+}
+
+fn whois(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	use chrono::Utc;
+
+	let name = Arc::from(args[0]);
+	let (tz, clock) = time_prefs(ctx.user);
+	let buf = {
+		let server = SERVER.read();
+		let user = server.users.get(&name)
+			.ok_or(CommandError::NotFound)?
+			.lock().unwrap();
+
+		let fmt_time = |time: u64| {
+			let time = local_time(time, tz);
+			format!("{} ({BOLD}{}{RESET} ago)",
+				time.format(&format!("{BOLD}{}{RESET} %B %d, %Y", clock.format(false))),
+				humantime::format_duration(std::time::Duration::from_secs(
+					Utc::now().signed_duration_since(time)
+						.to_std().unwrap().as_secs())))
+		};
+
+		// last_login gets stamped on the way out, so for offline users its when they were last around
+		let mut buf: Vec<u8> = match SERVER.online().get(&name) {
+			None if user.last_login == 0 => Vec::from(b"online: false\r\nlast-seen: never\r\n"),
+			None => format!("online: false\r\nlast-seen: {}\r\n", fmt_time(user.last_login)).into_bytes(),
+			Some(presence) => {
+				let mut buf = format!("online: {}\r\n", fmt_time(user.online_time));
+				if let Some(away) = &presence.away { buf += &format!("away: {away}\r\n"); }
+				buf.into_bytes()
 			},
-			["channel-perms", path] | ["lsperm", path] => {
-				let path = user.path.as_path().join(Path::new(path));
+		};
+		if let Some(bio) = &user.bio { buf.extend(format!("bio: {bio}\r\n").as_bytes()); }
+
+		// current-channel: (/path)
+		// buf.extend(b"current-channel: ");
+		// buf.extend(user.path.as_os_str().as_encoded_bytes());
+		// buf.extend(b"\r\n");
 
-				let channel = SERVER.read().channel_from_path(&path)
-					.ok_or(CommandError::InvalidPath)?;
+		if !user.roles.is_empty() {
+			buf.extend(b"roles:\r\n");
+			user.roles.iter().enumerate().for_each(|(index, (name, level))|
+				buf.extend(format!("{} {:?} - {:?}\r\n",
+				if index == user.roles.len() - 1  { "└──" } else { "├──" },
+				name, level).as_bytes()));
+		}
+
+		buf
+	};
+	ctx.user.info(&buf);
+	Ok(())
+}
+
+fn search(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	const MAX_RESULTS: usize = 20;
+
+	let query = args.join(" ").trim().to_lowercase();
+	if query.is_empty() { Err(CommandError::InvalidArgs)?; }
+
+	let (tz, clock) = time_prefs(ctx.user);
+	let format = format!("%Y-%m-%d {}", clock.format(false));
+	// newest ones are the likeliest to be what they're after
+	let found = ctx.user.channel.history.lock().unwrap()
+		.recent.iter().rev()
+		.filter(|m| m.msg.to_lowercase().contains(&query))
+		.take(MAX_RESULTS)
+		.map(|m| {
+			let time = local_time(m.time, tz);
+			format!("{BRIGHT_BLACK}{} #{}{RESET} {BOLD}{}{RESET}: {}\r\n", time.format(&format), m.id, m.from, plain(&m.msg))
+		})
+		.collect::<Vec<_>>();
+
+	match found.is_empty() {
+		true  => ctx.user.info(b"No matches.\r\n"),
+		false => ctx.user.info(found.into_iter().rev().collect::<String>().as_bytes()),
+	}
+	Ok(())
+}
+
+// a plain transcript, meant to be copied out or logged
+fn export(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let user = &mut *ctx.user;
+	let path = user.path.as_path().join(Path::new(args.first().unwrap_or(&"")));
+	let channel = SERVER.read().channel_at(&path)
+		.ok_or(CommandError::InvalidPath)?;
+
+	let perms = SERVER.read().perms_at(&path, &user.name, &user.config.lock().unwrap());
+	if !perms.can_read() { Err(CommandError::Forbidden)?; }
+	let (history, locked) = {
+		let channel = channel.read().unwrap();
+		(Arc::clone(&channel.history), channel.password.is_some() && user.channel.id != channel.id)
+	};
+	// being in there means they got past the password already, managers dont need it anyway
+	if locked && !perms.can_manage() { Err(CommandError::Forbidden)?; }
+
+	let (tz, clock) = time_prefs(user);
+	let format = format!("%Y-%m-%d {}", clock.format(true));
+	// only as much as is kept around, see History::LEN
+	let mut out = format!("== {} ==\r\n", path.display());
+	for m in history.lock().unwrap().recent.iter() {
+		let time = local_time(m.time, tz);
+		out += &format!("{} #{} {}: {}\r\n", time.format(&format), m.id, m.from, plain(&m.msg));
+	}
+
+	// not as info, so it stays put once they start typing again
+	user.conn.data(CryptoVec::from(out));
+	Ok(())
+}
+
+// whatever escapes people managed to type in dont belong in a transcript
+fn plain(msg: &str) -> String {
+	let mut out = String::with_capacity(msg.len());
+	let mut chars = msg.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			// colours and the like, ESC [ ... up to the final byte
+			'\x1b' if chars.clone().next() == Some('[') => 
+				{ chars.by_ref().skip(1).find(|c| ('@'..='~').contains(c)); },
+			'\n' => out += "\r\n", // pastes
+			c if c.is_control() => (),
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+fn find(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	// perms are what channel resolved to, handed down so inheriting children dont need to look back up
+	fn walk(path: &Path, channel: &Channel, perms: PermLevel, query: &str, user: (&str, &UserConfig), found: &mut Vec<String>) {
+		for (name, child) in &channel.children {
+			let child = child.read().unwrap();
+			let path = path.join(&**name);
+			let perms = child.resolve(user.0, user.1, perms);
+			// hidden ones still get looked inside, something under them might be open
+			if name.to_lowercase().contains(query) && child.effective(perms, user.1).can_read() 
+				{ found.push(format!("{}\r\n", path.display())); }
+			walk(&path, &child, perms, query, user, found);
+		}
+	}
+
+	let query = args[0].to_lowercase();
+	let mut found = Vec::new();
+	{
+		let config = ctx.user.config.lock().unwrap();
+		let root = Arc::clone(&SERVER.read().root_channel);
+		let root = root.read().unwrap();
+		let perms = root.resolve(&ctx.user.name, &config, PermLevel::NONE);
+		walk(Path::new("/"), &root, perms, &query, (&ctx.user.name, &config), &mut found);
+	}
+
+	found.sort_unstable();
+	match found.is_empty() {
+		true  => ctx.user.info(b"No channels found.\r\n"),
+		false => ctx.user.info(found.concat().as_bytes()),
+	}
+	Ok(())
+}
+
+// who got invited where is for the people keeping the channel, not everyone
+fn lsperm(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (_, channel) = moderated_channel(ctx.user, args[0])?;
+	let msg = format!("{:?}", channel.read().unwrap().perms);
+
+	ctx.user.info(msg.as_bytes());
+	Ok(())
+}
+
+// whoever is in there (or further down, they might inherit) gets moved out if they cant read anymore.
+// after the change is saved, so the generation has moved and their perms get worked out again
+fn perms_changed(channel: &Arc<RwLock<Channel>>)
+	{ Channel::for_each_mut(channel, &mut |c| { let _ = c.tx.send(Event::Perms); }); }
+
+// a channel they can hand out access to
+fn managed_channel(user: &User, path: &str) -> Result<(PathBuf, Arc<RwLock<Channel>>), CommandError>
+	{ channel_where(user, path, PermLevel::can_manage) }
+
+// one they get to keep people in line in
+fn moderated_channel(user: &User, path: &str) -> Result<(PathBuf, Arc<RwLock<Channel>>), CommandError>
+	{ channel_where(user, path, PermLevel::can_moderate) }
+
+fn channel_where(user: &User, path: &str, allowed: fn(PermLevel) -> bool) -> Result<(PathBuf, Arc<RwLock<Channel>>), CommandError> {
+	// exactly there, a typo shouldnt end up changing whatever is above it
+	let path = user.path.as_path().join(Path::new(path));
+	let channel = SERVER.read().channel_at(&path)
+		.ok_or(CommandError::InvalidPath)?;
+
+	if !allowed(SERVER.read().perms_at(&path, &user.name, &user.config.lock().unwrap()))
+		{ Err(CommandError::Forbidden)?; }
+	Ok((path, channel))
+}
+
+fn invite(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (path, channel) = managed_channel(ctx.user, args[0])?;
+	let level = match args.get(2) {
+		None          => PermLevel::READ|PermLevel::WRITE,
+		Some(&"read") => PermLevel::READ,
+		Some(_)       => Err(CommandError::InvalidArgs)?,
+	};
+
+	let name = Arc::<str>::from(args[1]);
+	if !SERVER.read().users.contains_key(&name) { Err(CommandError::NotFound)?; }
+
+	{
+		let perms = &mut channel.write().unwrap().perms;
+		if perms.iter().any(|(r, _)| matches!(r, RestrictionKind::User(u) if *u == name)) 
+			{ Err(CommandError::AlreadyExists)?; }
+		perms.push((RestrictionKind::User(Arc::clone(&name)), level));
+		perms.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+	}
+	SERVER.write().changed(); // so it gets saved
+	audit::record(&ctx.user.name, "invite", &format!("{name} to {}", path.display()));
+	Ok(())
+}
+
+// moderators can kick, but only managers get to kick other moderators
+fn kick_from(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (path, channel) = moderated_channel(ctx.user, args[0])?;
+	let manager = SERVER.read().perms_at(&path, &ctx.user.name, &ctx.user.config.lock().unwrap()).can_manage();
+
+	{
+		let perms = &mut channel.write().unwrap().perms;
+		let index = perms.iter()
+			.position(|(r, _)| matches!(r, RestrictionKind::User(u) if &**u == args[1]))
+			.ok_or(CommandError::NotFound)?;
+		if !manager && perms[index].1.can_moderate() { Err(CommandError::Forbidden)?; }
+		perms.remove(index);
+	}
+	SERVER.write().changed(); // so it gets saved
+	perms_changed(&channel);
+	audit::record(&ctx.user.name, "kick-from", &format!("{} from {}", args[1], path.display()));
+	Ok(())
+}
+
+fn announce(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError>
+	{ set_announce(ctx, args[0], true) }
+
+fn unannounce(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError>
+	{ set_announce(ctx, args[0], false) }
+
+fn inherit(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError>
+	{ set_inherit(ctx, args[0], true) }
+
+fn noinherit(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError>
+	{ set_inherit(ctx, args[0], false) }
 
-				let msg = format!("{:?}", channel.read().unwrap().perms);
+fn make_mod(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (path, channel) = managed_channel(ctx.user, args[0])?;
+	let name = Arc::<str>::from(args[1]);
+	if !SERVER.read().users.contains_key(&name) { Err(CommandError::NotFound)?; }
 
-				user.info(msg.as_bytes()).await;
+	{
+		let perms = &mut channel.write().unwrap().perms;
+		match perms.iter_mut().find(|(r, _)| matches!(r, RestrictionKind::User(u) if *u == name)) {
+			Some((_, level)) if level.contains(PermLevel::MODERATE) => Err(CommandError::AlreadyExists)?,
+			Some((_, level)) => *level |= PermLevel::MODERATE,
+			None => {
+				perms.push((RestrictionKind::User(Arc::clone(&name)), PermLevel::READ|PermLevel::WRITE|PermLevel::MODERATE));
+				perms.sort_unstable_by(|a, b| a.0.cmp(&b.0));
 			},
-			_ => Err(CommandError::InvalidCommand)?,
 		}
+	}
+	SERVER.write().changed(); // so it gets saved
+	audit::record(&ctx.user.name, "mod", &format!("{name} in {}", path.display()));
+	Ok(())
+}
 
-		data!(b"\x1b[2K\r");
-		user.buf_clear();
+// back to whatever else they had in there
+fn unmod(ctx: &mut Ctx, args: &[&str]) -> Result<(), CommandError> {
+	let (path, channel) = managed_channel(ctx.user, args[0])?;
 
-		Ok(())
+	{
+		let perms = &mut channel.write().unwrap().perms;
+		let (_, level) = perms.iter_mut()
+			.find(|(r, level)| matches!(r, RestrictionKind::User(u) if &**u == args[1]) && level.contains(PermLevel::MODERATE))
+			.ok_or(CommandError::NotFound)?;
+		level.remove(PermLevel::MODERATE);
+	}
+	SERVER.write().changed(); // so it gets saved
+	perms_changed(&channel);
+	audit::record(&ctx.user.name, "unmod", &format!("{} in {}", args[1], path.display()));
+	Ok(())
+}
+
+// no perms get touched, so turning it off puts back exactly what was there
+fn set_announce(ctx: &mut Ctx, path: &str, on: bool) -> Result<(), CommandError> {
+	let (path, channel) = managed_channel(ctx.user, path)?;
+	{
+		let mut channel = channel.write().unwrap();
+		if channel.announce == on { Err(CommandError::AlreadyExists)?; }
+		channel.announce = on;
+	}
+
+	SERVER.write().changed(); // so it gets saved, and everyones perms get looked at again
+	audit::record(&ctx.user.name, if on { "announce" } else { "unannounce" }, &path.to_string_lossy());
+	Ok(())
+}
+
+// everything under it inheriting too is up to each of them
+fn set_inherit(ctx: &mut Ctx, path: &str, on: bool) -> Result<(), CommandError> {
+	let (path, channel) = managed_channel(ctx.user, path)?;
+	{
+		let mut channel = channel.write().unwrap();
+		if channel.inherit == on { Err(CommandError::AlreadyExists)?; }
+		channel.inherit = on;
+	}
+
+	SERVER.write().changed(); // so it gets saved, and everyones perms get looked at again
+	perms_changed(&channel);
+	audit::record(&ctx.user.name, if on { "inherit" } else { "noinherit" }, &path.to_string_lossy());
+	Ok(())
+}
+
+impl crate::ChatClient {
+	pub fn command(
+		channel: ChannelId,
+		session: &mut Session,
+		data: &[u8],
+		user: &mut MutexGuard<'_, User>)
+	-> Result<(), CommandError> {
+		let cmd = std::str::from_utf8(data)
+			.map_err(|_| CommandError::InvalidUtf8)?
+			.split(' ').collect::<Vec<_>>();
+
+		let (name, args) = cmd.split_first().ok_or(CommandError::InvalidCommand)?;
+		let command = Command::find(name).ok_or(CommandError::InvalidCommand)?;
+
+		if !command.takes(args.len()) { Err(CommandError::InvalidArgs)?; }
+		if !command.allowed(&mut *user) { Err(CommandError::Forbidden)?; }
+
+		// clear out the command first, so whatever the handler leaves on the line (like a prompt) stays
+		let mut ctx = Ctx { session, channel, user };
+		ctx.data(b"\x1b[2K\r");
+		ctx.user.buf_clear();
+
+		(command.run)(&mut ctx, args)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// / and /a, with perms on /a
+	fn with_a(perms: Vec<(RestrictionKind, PermLevel)>, inherit: bool) -> Server {
+		let server = Server::default();
+		let mut a = Channel::new(server.new_channel_id());
+		(a.perms, a.inherit) = (perms, inherit);
+		server.root_channel.write().unwrap().children.insert(Box::from("a"), Arc::new(RwLock::new(a)));
+		server
+	}
+
+	fn roles(roles: &[PermLevel]) -> UserConfig
+		{ UserConfig { roles: roles.iter().map(|p| (Box::from("r"), *p)).collect(), ..Default::default() } }
+
+	#[test]
+	fn rmch_needs_manage() {
+		let a = Path::new("/a");
+		let bob = || RestrictionKind::User(Arc::from("bob"));
+		let rw = PermLevel::READ | PermLevel::WRITE;
+
+		let server = with_a(vec![(bob(), rw | PermLevel::MANAGE)], false);
+		assert!(can_remove(&server, a, "bob", &UserConfig::default()));
+		assert!(!can_remove(&server, a, "alice", &UserConfig::default()));
+
+		// moderating it, or everything short of manage, isnt enough
+		let server = with_a(vec![(bob(), rw | PermLevel::MODERATE)], false);
+		assert!(!can_remove(&server, a, "bob", &UserConfig::default()));
+
+		// managing / only counts where its inherited
+		for inherit in [false, true] {
+			let server = with_a(Vec::new(), inherit);
+			server.root_channel.write().unwrap().perms = vec![(bob(), rw | PermLevel::MANAGE)];
+			assert_eq!(can_remove(&server, a, "bob", &UserConfig::default()), inherit);
+		}
+
+		// managing a parent says nothing about a made up path under it
+		let server = with_a(vec![(bob(), rw | PermLevel::MANAGE)], false);
+		let mut x = Channel::new(server.new_channel_id());
+		x.perms = vec![(bob(), PermLevel::NONE)];
+		let a_channel = server.channel_at(a).unwrap();
+		a_channel.write().unwrap().children.insert(Box::from("x"), Arc::new(RwLock::new(x)));
+		assert!(!can_remove(&server, Path::new("/a/x"), "bob", &UserConfig::default()));
+		assert!(!can_remove(&server, Path::new("/a/bogus/x"), "bob", &UserConfig::default()));
+		assert!(!can_remove(&server, Path::new("/a/bogus"), "bob", &UserConfig::default()));
+
+		// global managers and admins can anywhere, other roles cant
+		let server = with_a(Vec::new(), false);
+		assert!(can_remove(&server, a, "alice", &roles(&[PermLevel::MANAGE])));
+		assert!(can_remove(&server, a, "alice", &roles(&[PermLevel::ADMIN])));
+		assert!(!can_remove(&server, a, "alice", &roles(&[rw | PermLevel::MODERATE])));
 	}
 }
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use crate::user::User;
+use crate::commands::{Command, Arg, COMMANDS};
+use crate::SERVER;
+
+pub enum Completion {
+	Insert(String), // goes in at the cursor
+	Candidates(Vec<String>),
+	Nothing,
+}
+
+// looks at everything up to the cursor and figures out what the word under it should be
+pub fn complete(user: &User) -> Completion {
+	let Ok(line) = std::str::from_utf8(&user.buffer[..user.cursor]) else { return Completion::Nothing; };
+	let Some(cmd) = line.strip_prefix(':') else { return complete_mention(line); };
+
+	let Some((name, args)) = cmd.split_once(' ') else {
+		let perms = user.config.lock().unwrap().get_global_perms();
+		let names = COMMANDS.iter().filter(|c| c.listed(perms)).flat_map(|c| c.names);
+		return complete_word(cmd, names.map(|n| n.to_string()), " ");
+	};
+	let Some(command) = Command::find(name) else { return Completion::Nothing; };
+
+	// only whole words get completed, so whatever is after the last space
+	let (index, word) = (args.matches(' ').count(), args.rsplit(' ').next().unwrap_or(""));
+	match command.args.get(index) {
+		Some(Arg::Path) => complete_path(user, word),
+		Some(Arg::User) => complete_user(word),
+		_ => Completion::Nothing,
+	}
+}
+
+fn complete_user(name: &str) -> Completion {
+	let online = SERVER.online().keys().map(|k| k.to_string()).collect::<Vec<_>>();
+	complete_word(name, online.into_iter(), " ")
+}
+
+// inline @name in a regular message
+fn complete_mention(line: &str) -> Completion {
+	match line.rsplit(' ').next().and_then(|word| word.strip_prefix('@')) {
+		Some(name) => complete_user(name),
+		None       => Completion::Nothing,
+	}
+}
+
+// only the last component gets completed, against the children of whatever comes before it
+fn complete_path(user: &User, path: &str) -> Completion {
+	let (parent, name) = match path.rsplit_once('/') {
+		Some(("", name))     => (Path::new("/"), name),
+		Some((parent, name)) => (Path::new(parent), name),
+		None                 => (Path::new(""), path),
+	};
+
+	let Some(channel) = SERVER.read().channel_from_path(&user.path.join(parent)) 
+		else { return Completion::Nothing; };
+	let mut children = channel.read().unwrap().children.keys()
+		.map(|k| k.to_string()).collect::<Vec<_>>();
+
+	// nothing typed yet, so offer their bookmarks too
+	if path.is_empty() 
+		{ children.extend(user.config.lock().unwrap().favorites.iter().map(|f| f.to_string())); }
+
+	complete_word(name, children.into_iter(), "/")
+}
+
+// a single match gets finished off (plus `end`), otherwise fill in whatever they all share
+fn complete_word(word: &str, candidates: impl Iterator<Item = String>, end: &str) -> Completion {
+	let mut matches = candidates.filter(|c| c.starts_with(word)).collect::<Vec<_>>();
+	matches.sort_unstable();
+	matches.dedup();
+
+	match matches.as_slice() {
+		[] => Completion::Nothing,
+		[only] => Completion::Insert(format!("{}{end}", &only[word.len()..])),
+		[first, rest @ ..] => {
+			let common = rest.iter().fold(first.len(), |len, m| first.bytes().zip(m.bytes())
+				.take(len).take_while(|(a, b)| a == b).count());
+
+			match common > word.len() && first.is_char_boundary(common) {
+				true  => Completion::Insert(String::from(&first[word.len()..common])),
+				false => Completion::Candidates(matches),
+			}
+		},
+	}
+}
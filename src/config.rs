@@ -0,0 +1,195 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::str::FromStr;
+use std::fmt;
+
+use crate::server::{StateFormat, Backups};
+use crate::irc::IrcConfig;
+use crate::webhook::Webhooks;
+use crate::bot::BotKind;
+use crate::user::{CtrlC, Clock, Prompt};
+use crate::channel::{PermEntry, PermSpec};
+
+// everything is read from the env for now
+pub struct Config {
+	pub bind_addr: SocketAddr,
+	pub log_level: log::LevelFilter,
+	pub key_file:  PathBuf, // the main host key, made on first run
+	pub key_dir:   Option<PathBuf>, // extra host keys, on top of the main one
+
+	pub inactivity_timeout:  Option<Duration>,
+	pub idle_warning:        Option<Duration>, // how long before the timeout to warn
+	pub auth_rejection_time: Duration,
+	pub auth_methods:        Vec<AuthMethod>, // what gets advertised, never empty
+	pub max_connections:     Option<usize>, // logged in or not
+	pub max_sessions:        Option<usize>, // per account
+
+	pub allow_registration: bool,
+	pub allow_guest:        bool,
+	pub guest_name:         String, // log in as this with any password for a read only look around
+	#[cfg(feature = "totp")]
+	pub totp:               bool, // lets people turn on two factor auth
+	pub ctrl_c:             CtrlC, // for users that didnt pick
+	pub prompt:             Prompt, // same
+	pub clock:              Clock, // same, 12 or 24 hour timestamps
+	pub default_channel:    PathBuf, // where everyone starts out
+	pub channel_perms:      Vec<PermEntry>, // what mkch gives new channels, on top of the creator managing it
+	pub edit_window:        Option<Duration>, // how long messages stay editable
+
+	pub state_file:        PathBuf,
+	pub state_format:      StateFormat,
+	pub backups:           Option<Backups>,
+	pub snapshot_interval: Option<Duration>, // saves everything this often even when nothing looked changed
+
+	pub audit_log: Option<PathBuf>,
+	pub log_dir:   Option<PathBuf>, // chat logs
+
+	pub metrics_addr: Option<SocketAddr>,
+	#[cfg(feature = "websocket")]
+	pub ws_addr:      Option<SocketAddr>,
+
+	pub irc:      Option<IrcConfig>,
+	pub webhooks: Option<Webhooks>,
+
+	pub bots:       Vec<BotKind>,
+	pub bot_prefix: String,
+}
+
+pub struct ConfigError {
+	var:    &'static str,
+	value:  String,
+	reason: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+	Password,
+	PublicKey,
+}
+
+impl FromStr for AuthMethod {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"password"  => Ok(Self::Password),
+			"publickey" => Ok(Self::PublicKey),
+			_ => Err(format!("expected password or publickey, got {s:?}")),
+		}
+	}
+}
+
+// comma separated, blanks get skipped
+pub struct List<T>(pub Vec<T>);
+
+impl<T: FromStr> FromStr for List<T> {
+	type Err = T::Err;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		s.split(',').map(str::trim).filter(|s| !s.is_empty())
+			.map(str::parse).collect::<Result<_, _>>().map(Self)
+	}
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+		{ write!(f, "invalid {}={:?}: {}", self.var, self.value, self.reason) }
+}
+
+fn parse<T: FromStr>(var: &'static str, value: String) -> Result<T, ConfigError>
+where T::Err: fmt::Display 
+	{ value.parse().map_err(|e: T::Err| ConfigError { var, reason: e.to_string(), value }) }
+
+// read `name` from the env, falling back to `default` when unset
+fn var<T: FromStr>(name: &'static str, default: &str) -> Result<T, ConfigError>
+where T::Err: fmt::Display 
+	{ parse(name, std::env::var(name).unwrap_or_else(|_| String::from(default))) }
+
+fn opt_var<T: FromStr>(name: &'static str) -> Result<Option<T>, ConfigError>
+where T::Err: fmt::Display 
+	{ std::env::var(name).ok().map(|value| parse(name, value)).transpose() }
+
+impl Config {
+	pub fn from_env() -> Result<Self, ConfigError> {
+		let inactivity_timeout = var("INACTIVITY_TIMEOUT", "3600")?;
+		let idle_warning = var("IDLE_WARNING", "60")?;
+		let edit_window = var("EDIT_WINDOW", "900")?;
+		let backup_count: usize = var("BACKUP_COUNT", "5")?;
+		let max_connections = var("MAX_CONNECTIONS", "0")?;
+		let max_sessions = var("MAX_SESSIONS", "0")?;
+		let snapshot_interval = var("SNAPSHOT_INTERVAL", "0")?;
+
+		let List(auth_methods) = var("AUTH_METHODS", "password")?;
+		if auth_methods.is_empty() {
+			return Err(ConfigError { var: "AUTH_METHODS", value: String::new(), reason: String::from("need at least one") });
+		}
+
+		Ok(Self {
+			bind_addr: var("BIND_ADDR", "0.0.0.0:2222")?,
+			log_level: var("LOG_LEVEL", "info")?,
+			key_file:  var("KEY_FILE", "key")?,
+			key_dir:   opt_var("KEY_DIR")?,
+
+			// 0 turns it off
+			inactivity_timeout:  (inactivity_timeout != 0).then(|| Duration::from_secs(inactivity_timeout)),
+			idle_warning:        (idle_warning != 0).then(|| Duration::from_secs(idle_warning)),
+			auth_rejection_time: Duration::from_secs(var("AUTH_REJECTION_TIME", "2")?),
+			auth_methods,
+			max_connections: (max_connections != 0).then_some(max_connections),
+			max_sessions:    (max_sessions != 0).then_some(max_sessions),
+
+			allow_registration: var("ALLOW_REGISTRATION", "false")?,
+			allow_guest:        var("ALLOW_GUEST", "false")?,
+			guest_name:         var("GUEST_NAME", "guest")?,
+			#[cfg(feature = "totp")]
+			totp:               var("TOTP", "false")?,
+			ctrl_c:             var("CTRL_C", "quit")?,
+			prompt:             var("PROMPT", "")?,
+			clock:              var("CLOCK", "24")?,
+			default_channel:    var("DEFAULT_CHANNEL", "/")?,
+			channel_perms:      var::<List<PermSpec>>("DEFAULT_CHANNEL_PERMS", "all=read|write")?.0
+				.into_iter().map(|PermSpec(entry)| entry).collect(),
+			edit_window:        (edit_window != 0).then(|| Duration::from_secs(edit_window)),
+
+			state_file:   var("STATE_FILE", "state.bin")?,
+			state_format: var("STATE_FORMAT", "bincode")?,
+			backups: match backup_count {
+				0    => None,
+				keep => Some(Backups {
+					dir:      opt_var("BACKUP_DIR")?,
+					interval: Duration::from_secs(var("BACKUP_INTERVAL", "3600")?),
+					keep,
+				}),
+			},
+			snapshot_interval: (snapshot_interval != 0).then(|| Duration::from_secs(snapshot_interval)),
+
+			audit_log: opt_var("AUDIT_LOG")?,
+			log_dir:   opt_var("LOG_DIR")?,
+
+			metrics_addr: opt_var("METRICS_ADDR")?,
+			#[cfg(feature = "websocket")]
+			ws_addr:      opt_var("WS_ADDR")?,
+
+			// the rest only matters once theres a server to bridge to
+			irc:      match opt_var("IRC_SERVER")? {
+				None         => None,
+				Some(server) => Some(IrcConfig {
+					server,
+					channel: var("IRC_CHANNEL", "#crussh")?,
+					nick:    var("IRC_NICK", "crussh")?,
+					bridge:  var("IRC_BRIDGE", "/")?,
+				}),
+			},
+			webhooks: match var::<List<String>>("WEBHOOK_URLS", "")? {
+				List(urls) if urls.is_empty() => None,
+				List(urls) => Some(Webhooks {
+					urls,
+					channels: var::<List<_>>("WEBHOOK_CHANNELS", "/")?.0,
+					events:   var::<List<_>>("WEBHOOK_EVENTS", "msg,reply,edit,delete,join,leave")?.0,
+				}),
+			},
+
+			bots:       var::<List<_>>("BOTS", "")?.0,
+			bot_prefix: var("BOT_PREFIX", "!")?,
+		})
+	}
+}
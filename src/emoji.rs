@@ -0,0 +1,89 @@
+// :shortcode: to emoji, done once a message is sent so editing the line stays simple
+use std::borrow::Cow;
+
+// sorted, so it can be binary searched
+const SHORTCODES: &[(&str, &str)] = &[
+	("+1",               "👍"),
+	("-1",               "👎"),
+	("100",              "💯"),
+	("angry",            "😠"),
+	("beer",             "🍺"),
+	("blush",            "😊"),
+	("broken_heart",     "💔"),
+	("bug",              "🐛"),
+	("cat",              "🐱"),
+	("check",            "✅"),
+	("clap",             "👏"),
+	("coffee",           "☕"),
+	("cool",             "😎"),
+	("cry",              "😢"),
+	("dog",              "🐶"),
+	("eyes",             "👀"),
+	("fire",             "🔥"),
+	("frown",            "🙁"),
+	("grin",             "😁"),
+	("heart",            "❤️"),
+	("hourglass",        "⌛"),
+	("joy",              "😂"),
+	("kiss",             "😘"),
+	("laughing",         "😆"),
+	("lock",             "🔒"),
+	("ok_hand",          "👌"),
+	("party",            "🥳"),
+	("pizza",            "🍕"),
+	("point_up",         "☝️"),
+	("pray",             "🙏"),
+	("question",         "❓"),
+	("rocket",           "🚀"),
+	("rofl",             "🤣"),
+	("sad",              "😞"),
+	("scream",           "😱"),
+	("shrug",            "🤷"),
+	("skull",            "💀"),
+	("sleeping",         "😴"),
+	("smile",            "😄"),
+	("smirk",            "😏"),
+	("sob",              "😭"),
+	("sparkles",         "✨"),
+	("star",             "⭐"),
+	("sunglasses",       "😎"),
+	("tada",             "🎉"),
+	("thinking",         "🤔"),
+	("thumbsdown",       "👎"),
+	("thumbsup",         "👍"),
+	("upside_down",      "🙃"),
+	("warning",          "⚠️"),
+	("wave",             "👋"),
+	("wink",             "😉"),
+	("x",                "❌"),
+	("zap",              "⚡"),
+];
+
+fn lookup(code: &str) -> Option<&'static str> {
+	SHORTCODES.binary_search_by(|(c, _)| c.cmp(&code)).ok().map(|i| SHORTCODES[i].1)
+}
+
+// unknown codes and lone colons (like in 12:30) are left as they are
+pub fn expand(msg: &str) -> Cow<'_, str> {
+	if !msg.contains(':') { return Cow::Borrowed(msg); }
+
+	let mut out = String::with_capacity(msg.len());
+	let mut rest = msg;
+	while let Some(start) = rest.find(':') {
+		out += &rest[..start];
+		rest = &rest[start + 1..];
+
+		let code = rest.find(':').map(|end| &rest[..end])
+			.filter(|code| code.bytes().all(|b| b.is_ascii_alphanumeric() || b"_+-".contains(&b)))
+			.and_then(|code| Some((code, lookup(code)?)));
+		match code {
+			Some((code, emoji)) => {
+				out += emoji;
+				rest = &rest[code.len() + 1..];
+			},
+			None => out.push(':'),
+		}
+	}
+	out += rest;
+	Cow::Owned(out)
+}
@@ -1,9 +1,13 @@
 use std::sync::Arc;
+use serde_json::json;
 use colour::*;
 
 pub mod colour {
 	pub const BOLD: &str = "\x1b[1m";
 	pub const ITALIC: &str = "\x1b[3m";
+	pub const UNDERLINE: &str = "\x1b[4m";
+	pub const REVERSE: &str = "\x1b[7m";
+	pub const BLUE: &str = "\x1b[34m";
 	pub const BRIGHT_BLACK: &str = "\x1b[90m";
 	pub const RED: &str = "\x1b[31m";
 	pub const RESET: &str = "\x1b[0m";
@@ -11,27 +15,110 @@ pub mod colour {
 
 type Uname = Arc<str>;
 type Msg   = Arc<str>;
+pub type MsgId = u64; // per channel, handed out by SubscribedChannel::send
+
+// for text from outside ssh (irc, websockets), where nothing stopped line breaks or escapes getting in
+pub fn no_controls(msg: &str) -> Msg
+	{ Arc::from(msg.replace(|c: char| c.is_control(), "")) }
 
 #[derive(Clone, Debug)]
 pub enum Event {
-	Msg(Uname, Msg),
-	Reply(Uname, Uname, Msg),
+	Msg(MsgId, Uname, Msg),
+	Reply(MsgId, Uname, Uname, Msg, Option<Quote>), // last one is what's being replied to
+	Edit(MsgId, Uname, Msg), // uname is the original author
+	Delete(MsgId),
 
 	Join(Uname),
 	Leave(Uname),
 
-	Terminate,
+	Typing(Uname), // never kept anywhere, just drawn for a bit
+	Perms, // who can get in changed, everyone in there works theirs out again
+
+	Removed, // the channel is gone, last thing it ever sends
+}
+
+#[derive(Clone, Debug)]
+pub struct Quote {
+	pub id:     MsgId,
+	pub msg:    Msg,
+	pub thread: Arc<[Uname]>, // who that one was replying to and so on up, nearest first. see History::thread
 }
 
+// how a user sees it, with or without their markdown rendered
+pub struct Styled<'a>(&'a Event, bool);
+
 impl std::fmt::Display for Event {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+		{ self.styled(true).fmt(f) }
+}
+
+impl std::fmt::Display for Styled<'_> {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-		match self {
-			Event::Msg(uname, msg) => write!(f, "{BOLD}{uname}{RESET}: {msg}"),
+		let Styled(event, markdown) = *self;
+		// code blocks start on the next line and are left exactly as they were
+		let text = |msg: &Msg| match crate::markdown::fenced(msg) {
+			Some(code) => code.split('\n').fold(String::new(), |s, line| s + "\r\n" + BRIGHT_BLACK + "│" + RESET + " " + line),
+			None if markdown => crate::markdown::render(msg).into_owned(),
+			None => msg.to_string(),
+		};
+		match event {
+			Event::Msg(id, uname, msg) => write!(f, "{BRIGHT_BLACK}{id}{RESET} {BOLD}{uname}{RESET}: {}", text(msg)),
+			Event::Edit(id, uname, msg) => write!(f,
+				"{BRIGHT_BLACK}{id}{RESET} {BOLD}{uname}{RESET}: {} {BRIGHT_BLACK}(edited){RESET}", text(msg)),
+			Event::Delete(id) => write!(f, "{BRIGHT_BLACK}{id} [message deleted]{RESET}"),
 			Event::Join(uname)     => write!(f, "[{BOLD}{uname}{RESET} joined]"),
 			Event::Leave(uname)    => write!(f, "[{BOLD}{uname}{RESET} left]"),
-			Event::Reply(from, to, msg) => 
-				write!(f, "{BOLD}{from}{RESET} {ITALIC}{BRIGHT_BLACK}to{RESET} {BOLD}{to}{RESET}: {msg}"),
-			Event::Terminate => unreachable!(),
+			Event::Typing(uname)   => write!(f, "[{BOLD}{uname}{RESET} is typing…]"),
+			Event::Perms           => write!(f, "[permissions changed]"),
+			Event::Removed         => write!(f, "[channel removed]"),
+			Event::Reply(id, from, to, msg, quote) => {
+				const MAX_QUOTE: usize = 60;
+				const MAX_THREAD: usize = 3;
+				if let Some(Quote { msg: quote, thread, .. }) = quote {
+					// oldest first, so it reads the way the conversation went
+					write!(f, "{BRIGHT_BLACK}│ ")?;
+					if thread.len() > MAX_THREAD { write!(f, "{ITALIC}… → ")?; }
+					thread.iter().take(MAX_THREAD).rev().try_for_each(|name| write!(f, "{ITALIC}{name} → "))?;
+
+					let quote = quote.replace('\n', " ");
+					match quote.char_indices().nth(MAX_QUOTE) {
+						Some((end, _)) => write!(f, "{RESET}{BRIGHT_BLACK}{to}: {}…{RESET}\r\n", &quote[..end])?,
+						None           => write!(f, "{RESET}{BRIGHT_BLACK}{to}: {quote}{RESET}\r\n")?,
+					}
+				}
+				write!(f, "{BRIGHT_BLACK}{id}{RESET} {BOLD}{from}{RESET} {ITALIC}{BRIGHT_BLACK}to{RESET} {BOLD}{to}{RESET}: {}", text(msg))
+			},
+		}
+	}
+}
+
+impl Event {
+	pub fn styled(&self, markdown: bool) -> Styled<'_>
+		{ Styled(self, markdown) }
+
+	// the id is a placeholder until the message gets sent
+	pub fn msg(from: Uname, msg: Msg) -> Self 
+		{ Event::Msg(0, from, msg) }
+
+	pub fn reply(from: Uname, to: Uname, msg: Msg, quote: Option<Quote>) -> Self 
+		{ Event::Reply(0, from, to, msg, quote) }
+
+	// what the outside world (websockets, webhooks) gets to see
+	pub fn to_json(&self) -> serde_json::Value {
+		match self {
+			Event::Msg(id, from, msg)       => json!({ "type": "msg", "id": id, "from": from, "msg": msg }),
+			Event::Reply(id, from, to, msg, quote) => json!({
+				"type": "reply", "id": id, "from": from, "to": to, "msg": msg,
+				"quote": quote.as_ref().map(|q| &q.msg), "reply_to": quote.as_ref().map(|q| q.id),
+				"thread": quote.as_ref().map(|q| &*q.thread),
+			}),
+			Event::Edit(id, from, msg)      => json!({ "type": "edit", "id": id, "from": from, "msg": msg }),
+			Event::Delete(id)               => json!({ "type": "delete", "id": id }),
+			Event::Join(name)               => json!({ "type": "join", "user": name }),
+			Event::Leave(name)              => json!({ "type": "leave", "user": name }),
+			Event::Typing(name)             => json!({ "type": "typing", "user": name }),
+			Event::Perms                    => json!({ "type": "perms" }),
+			Event::Removed                  => json!({ "type": "removed" }),
 		}
 	}
 }
@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use log::{info, warn};
+
+use crate::channel::Channel;
+use crate::event::{Event, no_controls};
+use crate::SERVER;
+
+// what people on the irc side show up as, in front of their nick
+const IRC_PREFIX: &str = "irc:";
+
+#[derive(Clone)]
+pub struct IrcConfig {
+	pub server:  String, // host:port, plaintext only
+	pub channel: String, // #something
+	pub nick:    String,
+	pub bridge:  PathBuf, // crussh channel to bridge with
+}
+
+// keeps the bridge up forever, backing off between reconnects
+pub async fn bridge(config: IrcConfig) {
+	const MIN_BACKOFF: Duration = Duration::from_secs(5);
+	const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+	let mut backoff = MIN_BACKOFF;
+	loop {
+		match run(&config).await {
+			Ok(true)  => backoff = MIN_BACKOFF, // got far enough to join, start over fresh
+			Ok(false) => (),
+			Err(e)    => warn!("irc bridge to {}: {e}", config.server),
+		}
+
+		info!("irc bridge reconnecting in {}s", backoff.as_secs());
+		tokio::time::sleep(backoff).await;
+		backoff = (backoff * 2).min(MAX_BACKOFF);
+	}
+}
+
+// Ok(joined) once the connection drops
+async fn run(config: &IrcConfig) -> std::io::Result<bool> {
+	let Some(channel) = SERVER.read().channel_from_path(&config.bridge)
+		else { return Err(std::io::Error::other(format!("no channel at {}", config.bridge.display()))); };
+	let mut channel = Channel::subscribe(&channel);
+
+	let (rd, mut wr) = TcpStream::connect(&config.server).await?.into_split();
+	let mut lines = BufReader::new(rd).lines();
+
+	wr.write_all(format!("NICK {0}\r\nUSER {0} 0 * :crussh bridge\r\n", config.nick).as_bytes()).await?;
+	info!("irc bridge connected to {}", config.server);
+
+	let mut joined = false;
+	// events we sent ourselves come back around, spot them by their (shared) message Arc
+	let mut echoes = std::collections::VecDeque::<Arc<str>>::new();
+
+	loop {
+		tokio::select! {
+			line = lines.next_line() => {
+				let Some(line) = line? else { return Ok(joined); };
+				let (prefix, rest) = match line.strip_prefix(':') {
+					Some(line) => line.split_once(' ').unwrap_or((line, "")),
+					None       => ("", line.as_str()),
+				};
+
+				match rest.split_once(' ').unwrap_or((rest, "")) {
+					("PING", token) => wr.write_all(format!("PONG {token}\r\n").as_bytes()).await?,
+					// welcome, we're registered
+					("001", _) => wr.write_all(format!("JOIN {}\r\n", config.channel).as_bytes()).await?,
+					("JOIN", _) => joined = true,
+					("PRIVMSG", args) => {
+						let Some((target, msg)) = args.split_once(" :") else { continue; };
+						if !target.eq_ignore_ascii_case(&config.channel) { continue; }
+
+						// : cant be in a local name, so nobody over there can pass for someone here
+						let nick = prefix.split('!').next().unwrap_or(prefix);
+						let from = Arc::<str>::from(format!("{IRC_PREFIX}{nick}"));
+						let msg = no_controls(msg);
+						echoes.push_back(Arc::clone(&msg));
+						let _ = channel.send(Event::msg(from, msg));
+					},
+					_ => (),
+				}
+			},
+			event = channel.rx.recv() => match event {
+				Ok(Event::Msg(_, name, msg)) => {
+					if echoes.front().is_some_and(|e| Arc::ptr_eq(e, &msg)) {
+						echoes.pop_front();
+						continue;
+					}
+					if !joined { continue; }
+
+					// a newline in there would end the PRIVMSG early, so pastes go a line at a time
+					for line in msg.lines() {
+						for chunk in chunks(&format!("<{name}> {line}"), 400)
+							{ wr.write_all(format!("PRIVMSG {} :{chunk}\r\n", config.channel).as_bytes()).await?; }
+					}
+				},
+				// reconnecting picks up whatever IRC_BRIDGE points at now
+				Ok(Event::Removed) => return Ok(joined),
+				Ok(_) => (),
+				// whatever got lost is lost, dont let the echo tracking get confused by it
+				Err(RecvError::Lagged(_)) => echoes.clear(),
+				Err(RecvError::Closed) => return Ok(joined),
+			},
+		}
+	}
+}
+
+// irc lines cap out at 512 bytes, so split long messages on char boundaries
+fn chunks(msg: &str, max: usize) -> impl Iterator<Item = &str> {
+	let mut rest = msg;
+	std::iter::from_fn(move || {
+		if rest.is_empty() { return None; }
+		let mut end = rest.len().min(max);
+		while !rest.is_char_boundary(end) { end -= 1; }
+		let (chunk, tail) = rest.split_at(end);
+		rest = tail;
+		Some(chunk)
+	})
+}
@@ -0,0 +1,597 @@
+use std::time::Duration;
+use std::sync::{Arc, LazyLock};
+use std::sync::atomic::Ordering;
+use std::mem;
+use std::path::Path;
+use tokio::sync::Mutex;
+
+use russh::server::{Server as SshServer, Msg, Session, Handler, Auth};
+use russh::{MethodSet, CryptoVec, ChannelId, SshId};
+use russh::keys::{PrivateKey, PublicKey, Algorithm, HashAlg};
+use russh::keys::ssh_key::LineEnding;
+use log::{info, warn};
+
+mod user;
+pub mod channel;
+pub mod event;
+pub mod server;
+mod commands;
+mod config;
+mod audit;
+mod chatlog;
+mod metrics;
+mod irc;
+mod webhook;
+mod bot;
+mod complete;
+mod emoji;
+mod markdown;
+mod wrap;
+#[cfg(feature = "websocket")]
+mod ws;
+#[cfg(feature = "totp")]
+mod totp;
+
+use user::{User, UserConfig, UserConfLock, Connection, UserState, CtrlC};
+use server::ServerSerializer;
+use config::{Config, AuthMethod};
+use event::Event;
+use complete::Completion;
+
+static SERVER: LazyLock<ServerSerializer> = 
+	LazyLock::new(|| ServerSerializer::new(&CONFIG.state_file, CONFIG.state_format, CONFIG.backups.clone()));
+
+static CONFIG: LazyLock<Config> = 
+	LazyLock::new(|| Config::from_env().unwrap_or_else(|e| {
+		eprintln!("Error loading config: {e}");
+		std::process::exit(1);
+	}));
+
+pub async fn run() {
+	// bail on a bad config before touching anything else
+	LazyLock::force(&CONFIG);
+
+	env_logger::Builder::new()
+		.filter_level(log::LevelFilter::Warn) // russh gets chatty
+		.filter_module(module_path!(), CONFIG.log_level)
+		.init();
+
+	if let Some(dir) = &CONFIG.log_dir { chatlog::start(dir.clone()); }
+	if let Some(path) = &CONFIG.audit_log { audit::start(path.clone()); }
+	if let Some(addr) = CONFIG.metrics_addr { tokio::spawn(metrics::serve(addr)); }
+	#[cfg(feature = "websocket")]
+	if let Some(addr) = CONFIG.ws_addr { tokio::spawn(ws::serve(addr)); }
+	if let Some(irc) = &CONFIG.irc { tokio::spawn(irc::bridge(irc.clone())); }
+	if let Some(hooks) = &CONFIG.webhooks { webhook::start(hooks); }
+	for bot in &CONFIG.bots { SERVER.register_bot(bot.build()); }
+	if let Some(every) = CONFIG.snapshot_interval { tokio::spawn(SERVER.snapshots(every)); }
+
+	let key = host_key(&CONFIG.key_file).unwrap_or_else(|e| {
+		eprintln!("Error loading host key: {e}");
+		std::process::exit(1);
+	});
+	let mut keys = vec![key];
+	if let Some(dir) = &CONFIG.key_dir { keys.extend(extra_host_keys(dir)); }
+
+	let config = russh::server::Config {
+		server_id:                   SshId::Standard(format!("SSH-2.0-crussh_{}", env!("CARGO_PKG_VERSION"))),
+		inactivity_timeout:          CONFIG.inactivity_timeout,
+		auth_rejection_time:         CONFIG.auth_rejection_time,
+		auth_rejection_time_initial: Some(Duration::from_secs(0)),
+		keys,
+		methods:                     auth_methods(),
+		..Default::default()
+	};
+
+	ChatClient::new()
+		.run_on_address(Arc::new(config), CONFIG.bind_addr)
+		.await.unwrap_or_else(|e| panic!("Error listening on {}: {e}", CONFIG.bind_addr));
+}
+
+// this one counts too, so its over and not at
+fn over_limit(uname: &str) -> bool {
+	let connections = metrics::CONNECTIONS.load(Ordering::Relaxed) as usize;
+	let over = CONFIG.max_connections.is_some_and(|max| connections > max);
+	if over { warn!("turning {uname} away, {connections} connections is over MAX_CONNECTIONS"); }
+	over
+}
+
+fn auth_methods() -> MethodSet {
+	CONFIG.auth_methods.iter().fold(MethodSet::empty(), |set, method| set | match method {
+		AuthMethod::Password  => MethodSet::PASSWORD,
+		AuthMethod::PublicKey => MethodSet::PUBLICKEY,
+	})
+}
+
+// generate a fresh ed25519 key on first run instead of refusing to start
+fn host_key(path: &Path) -> Result<PrivateKey, String> {
+	if path.exists() {
+		if !path.is_file() { return Err(format!("key file at {} isnt a file", path.display())); }
+		return PrivateKey::read_openssh_file(path)
+			.map_err(|e| format!("key file at {} isnt an openssh private key: {e}", path.display()));
+	}
+
+	let key = PrivateKey::random(&mut rand::rngs::OsRng, Algorithm::Ed25519)
+		.map_err(|e| format!("couldnt generate a host key: {e}"))?;
+	key.write_openssh_file(path, LineEnding::LF)
+		.map_err(|e| format!("couldnt write a new key file at {}: {e}", path.display()))?;
+
+	warn!("no key file at {}, generated a new host key: {}",
+		path.display(), key.fingerprint(HashAlg::Sha256));
+	Ok(key)
+}
+
+// every private key in `dir`, bad ones get skipped so a stray file cant stop startup
+fn extra_host_keys(dir: &Path) -> Vec<PrivateKey> {
+	let mut paths = match std::fs::read_dir(dir) {
+		Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path()))
+			.filter(|p| p.is_file() && p.extension().is_none_or(|e| e != "pub"))
+			.collect::<Vec<_>>(),
+		Err(e) => {
+			warn!("skipping key dir {}: {e}", dir.display());
+			return Vec::new();
+		},
+	};
+	paths.sort_unstable();
+
+	paths.iter().filter_map(|path| PrivateKey::read_openssh_file(path)
+		.inspect_err(|e| warn!("skipping key file {}: {e}", path.display()))
+		.ok())
+		.collect()
+}
+
+// where a connection is at, theres only a User once they open a session
+enum ClientState {
+	Connected,
+	#[cfg(feature = "totp")]
+	NeedsCode { name: Arc<str>, config: UserConfLock }, // password was right, 2fa is next
+	Authed { name: Arc<str>, config: UserConfLock },
+	Active(Arc<Mutex<User>>),
+}
+
+struct ChatClient(ClientState);
+
+impl SshServer for ChatClient {
+	type Handler = Self;
+	fn new_client(&mut self, addr: Option<std::net::SocketAddr>) -> Self {
+		match addr {
+			Some(addr) => info!("connection from {addr}"),
+			None       => info!("connection from an unknown address"),
+		}
+		metrics::CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+		Self::new()
+	}
+}
+
+impl Drop for ChatClient {
+	fn drop(&mut self) {
+		metrics::CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+		let ClientState::Active(user) = &self.0 else { return; };
+
+		// cant await in here, so let the runtime take care of it instead of blocking a worker
+		let user = Arc::clone(user);
+		tokio::spawn(async move {
+			// usually already done by close or channel_close, the User itself goes with the last Arc
+			Self::leave(&mut *user.lock().await);
+		});
+	}
+}
+
+impl ChatClient {
+	fn new() -> Self { Self(ClientState::Connected) }
+
+	// whichever way they proved it, 2fa still wants its code after
+	fn logged_in(&mut self, uname: &str, valid: Option<Arc<std::sync::Mutex<UserConfig>>>) -> Auth {
+		match valid {
+			#[cfg(feature = "totp")]
+			Some(user) if CONFIG.totp && user.lock().unwrap().totp.is_some() => {
+				self.0 = ClientState::NeedsCode { name: Arc::from(uname), config: user };
+				Auth::Reject { proceed_with_methods: Some(MethodSet::KEYBOARD_INTERACTIVE) }
+			},
+			Some(user) => {
+				self.0 = ClientState::Authed { name: Arc::from(uname), config: user };
+				info!("{uname} logged in");
+				Auth::Accept
+			},
+			_ => {
+				warn!("failed login for {uname}");
+				metrics::inc(&metrics::AUTH_FAILURES);
+				Auth::Reject { proceed_with_methods: Some(auth_methods()) }
+			},
+		}
+	}
+
+	// stop the session, announce it and go offline. 
+	// every way out ends up here, only the first one does anything
+	fn leave(user: &mut User) {
+		if !user.stop() { return; }
+
+		let _ = user.channel.send(Event::Leave(user.name.clone()));
+		user.mark_seen();
+		user.channel.part();
+
+		{ // go offline
+			SERVER.go_offline(&user.name, &user.conn);
+
+			// might have just been deleted
+			let mut server = SERVER.write();
+			if let Some(conf) = server.users.get(&user.name).cloned() {
+				let mut conf = conf.lock().unwrap();
+				conf.online_time = 0;
+				conf.last_login = chrono::Utc::now().timestamp() as u64;
+				server.changed();
+			}
+		}
+
+		info!("{} disconnected", user.name);
+	}
+
+	// 0 is what clients send when they dont know
+	async fn resize(&self, cols: u32, rows: u32) {
+		let ClientState::Active(user) = &self.0 else { return; };
+		user.lock().await.size = (cols > 0 && rows > 0).then_some((cols, rows));
+	}
+
+	fn close(
+		session: &mut Session,
+		channel: ChannelId, 
+		user: &mut tokio::sync::MutexGuard<'_, User>) {
+		Self::leave(user);
+
+		metrics::sent(1);
+		session.data(channel, CryptoVec::from_slice(b"\r")).unwrap();
+		session.close(channel).unwrap();
+	}
+}
+
+#[async_trait::async_trait]
+impl Handler for ChatClient {
+	type Error = russh::Error;
+
+	async fn channel_open_session(
+		&mut self,
+		channel: russh::Channel<Msg>,
+		session: &mut Session,
+	) -> Result<bool, Self::Error> {
+		let (name, conf) = match &self.0 {
+			ClientState::Authed { name, config } => (Arc::clone(name), Arc::clone(config)),
+			// prob not gonna happen, but just in case
+			ClientState::Connected => return Err(russh::Error::NotAuthenticated),
+			#[cfg(feature = "totp")]
+			ClientState::NeedsCode { .. } => return Err(russh::Error::NotAuthenticated),
+			ClientState::Active(_) => return Ok(false), // one session per connection
+		};
+
+		let conn = Arc::new(Connection::new(channel.id(), session.handle()));
+		let guest = conf.lock().unwrap().guest;
+
+		{ // go online. guests all share a name, so they dont count towards MAX_SESSIONS
+			if !SERVER.go_online(&name, &conn, CONFIG.max_sessions.filter(|_| !guest)) {
+				warn!("{name} already has {} sessions open", CONFIG.max_sessions.unwrap_or_default());
+				conn.data(CryptoVec::from_slice(b"You have too many sessions open already, close one first.\r\n"));
+				conn.close();
+				return Ok(true);
+			}
+			conf.lock().unwrap().online_time = chrono::Utc::now().timestamp() as u64;
+		}
+
+		let user = User::new(Arc::clone(&name), conf, Arc::clone(&conn));
+		self.0 = ClientState::Active(Arc::clone(&user));
+
+		conn.data(CryptoVec::from_slice(b"Welcome! :help for commands, ctrl-d to exit.\r\n"));
+		if guest { conn.data(CryptoVec::from_slice(b"You're a guest, so you can look around but not talk.\r\n")); }
+		user.lock().await.redraw();
+
+		// can sometimes fail cause order of conn isnt guaranteed
+		let _ = user.lock().await.channel
+			.send(Event::Join(name)); 
+
+		Ok(true)
+	}
+
+	// only the size is any use, its what info gets paged to
+	async fn pty_request(
+		&mut self,
+		channel: ChannelId,
+		_: &str,
+		cols: u32, rows: u32,
+		_: u32, _: u32,
+		_: &[(russh::Pty, u32)],
+		session: &mut Session,
+	) -> Result<(), Self::Error> {
+		self.resize(cols, rows).await;
+		session.channel_success(channel)
+	}
+
+	async fn window_change_request(
+		&mut self,
+		_: ChannelId,
+		cols: u32, rows: u32,
+		_: u32, _: u32,
+		_: &mut Session,
+	) -> Result<(), Self::Error> {
+		self.resize(cols, rows).await;
+		Ok(())
+	}
+
+	async fn channel_close(&mut self, _: ChannelId, _: &mut Session) 
+	-> Result<(), Self::Error> {
+		let ClientState::Active(user) = &self.0 else { return Ok(()); };
+		Self::leave(&mut *user.lock().await);
+		Ok(())
+	}
+
+	async fn auth_password(&mut self, uname: &str, pass: &str) -> Result<Auth, Self::Error> {
+		if over_limit(uname) { return Ok(Auth::Reject { proceed_with_methods: None }); }
+
+		// clients can try methods that werent offered
+		if !CONFIG.auth_methods.contains(&AuthMethod::Password)
+			{ return Ok(Auth::Reject { proceed_with_methods: Some(auth_methods()) }); }
+
+		// whatever the password, guests dont have one
+		if CONFIG.allow_guest && uname == CONFIG.guest_name {
+			let config = UserConfig { guest: true, ..Default::default() };
+			self.0 = ClientState::Authed { name: Arc::from(uname), config: Arc::new(std::sync::Mutex::new(config)) };
+			info!("{uname} logged in as a guest");
+			return Ok(Auth::Accept);
+		}
+
+		// hashing + the server lock are blocking, keep them off the async workers
+		let (name, pass) = (String::from(uname), String::from(pass));
+		let valid = tokio::task::spawn_blocking(move || SERVER.read().validate_pass(&name, &pass))
+			.await.unwrap_or(None);
+		Ok(self.logged_in(uname, valid))
+	}
+
+	// saves the client signing anything for keys that wont get them in, auth_publickey checks again anyway
+	async fn auth_publickey_offered(&mut self, uname: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+		let known = CONFIG.auth_methods.contains(&AuthMethod::PublicKey)
+			&& SERVER.read().validate_key(uname, key).is_some();
+		Ok(match known {
+			true  => Auth::Accept,
+			false => Auth::Reject { proceed_with_methods: Some(auth_methods()) },
+		})
+	}
+
+	async fn auth_publickey(&mut self, uname: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+		if over_limit(uname) { return Ok(Auth::Reject { proceed_with_methods: None }); }
+		if !CONFIG.auth_methods.contains(&AuthMethod::PublicKey)
+			{ return Ok(Auth::Reject { proceed_with_methods: Some(auth_methods()) }); }
+
+		let valid = SERVER.read().validate_key(uname, key);
+		Ok(self.logged_in(uname, valid))
+	}
+
+	// only ever the second step, after a password or key wanted a code
+	#[cfg(feature = "totp")]
+	async fn auth_keyboard_interactive(&mut self, uname: &str, _: &str, response: Option<russh::server::Response<'async_trait>>)
+	-> Result<Auth, Self::Error> {
+		let ClientState::NeedsCode { name, config } = &self.0
+			else { return Ok(Auth::Reject { proceed_with_methods: Some(auth_methods()) }); };
+		if &**name != uname { return Ok(Auth::Reject { proceed_with_methods: Some(auth_methods()) }); }
+
+		let Some(mut response) = response else {
+			return Ok(Auth::Partial {
+				name:         "".into(),
+				instructions: "".into(),
+				prompts:      vec![("code: ".into(), false)].into(),
+			});
+		};
+
+		let code = response.next().and_then(|code| String::from_utf8(code.to_vec()).ok()).unwrap_or_default();
+		let secret = config.lock().unwrap().totp.clone();
+		// turned off between the password and the code, start over rather than check against nothing
+		let Some(secret) = secret else {
+			self.0 = ClientState::Connected;
+			return Ok(Auth::Reject { proceed_with_methods: Some(auth_methods()) });
+		};
+		if !totp::verify(&secret, &code) {
+			warn!("wrong 2fa code for {uname}");
+			metrics::inc(&metrics::AUTH_FAILURES);
+			// back to the start, a right code alone gets you nowhere
+			self.0 = ClientState::Connected;
+			return Ok(Auth::Reject { proceed_with_methods: Some(auth_methods()) });
+		}
+
+		self.0 = ClientState::Authed { name: Arc::clone(name), config: Arc::clone(config) };
+		info!("{uname} logged in");
+		Ok(Auth::Accept)
+	}
+
+	async fn data(&mut self, channel: ChannelId, data: &[u8], session: &mut Session)
+	-> Result<(), Self::Error> {
+		macro_rules! data {
+			($data:expr) => {{ 
+				let data = $data;
+				let data: &[u8] = data.as_ref();
+				metrics::sent(data.len());
+				session.data(channel, CryptoVec::from_slice(data)).unwrap() 
+			}}}
+
+		let ClientState::Active(user) = &self.0 else { return Ok(()); };
+		let mut user = user.lock().await;
+		user.touch();
+		if commands::pong(&mut user, data) { return Ok(()); }
+
+		match data {
+			_ if matches!(user.state, UserState::More { .. }) => match data {
+				b" " | [13] => {
+					let UserState::More { page, rest } =
+						mem::replace(&mut user.state, UserState::Normal)
+						else { unreachable!(); };
+
+					user.clear_info(&page);
+					user.info(&rest);
+				},
+				b"q" | [3] | [4] => {
+					user.clear_shown();
+					user.state = UserState::Normal;
+					user.redraw();
+				},
+				_ => (),
+			},
+
+			_ if matches!(user.state, UserState::Paste(_)) => commands::paste_data(&mut user, data),
+
+			_ if matches!(user.state, UserState::Info(_)) => {
+				let UserState::Info(data) =
+					mem::replace(&mut user.state, UserState::Normal) 
+					else { unreachable!(); };
+
+				user.clear_info(&data);
+				user.redraw();
+			},
+
+			_ if matches!(user.state, UserState::Prompt { .. }) => {
+				let hidden = matches!(user.state, UserState::Prompt { hidden: true, .. });
+				match data {
+					[13] => {
+						let UserState::Prompt { on_submit, .. } =
+							mem::replace(&mut user.state, UserState::Normal)
+							else { unreachable!(); };
+
+						let line = mem::take(&mut user.buffer);
+						user.cursor = 0;
+						data!(b"\x1b[2K\r");
+						if let Err(e) = on_submit(&mut user, &line) 
+							{ user.info(e.to_string().as_bytes()); }
+						if matches!(user.state, UserState::Normal) { user.redraw(); }
+					},
+					[3] | [4] => { // never mind
+						user.state = UserState::Normal;
+						user.buf_clear();
+						user.redraw();
+					},
+					[127] => if !user.buffer.is_empty() {
+						let (bytes, cols) = user.char_before();
+						let len = user.buffer.len();
+						user.buffer.truncate(len - bytes);
+						user.cursor = user.buffer.len();
+						if !hidden { data!(format!("\x1b[{cols}D\x1b[{cols}P")); }
+					},
+					_ if data.first().is_some_and(|&b| b < 32) => (),
+					_ => if user.buffer.len() < 256 {
+						user.buffer.extend(data);
+						user.cursor = user.buffer.len();
+						if !hidden { data!(data); }
+					},
+				}
+			},
+
+			[12] => { // ctrl-l, unlike :clear this keeps whatever is being typed
+				data!(b"\x1b[2J\x1b[H");
+				user.redraw();
+			},
+
+			[9] => match complete::complete(&user) { // tab
+				Completion::Insert(text) => {
+					let cursor = user.cursor;
+					user.buffer.splice(cursor..cursor, text.bytes());
+					user.cursor += text.len();
+					user.redraw();
+				},
+				Completion::Candidates(candidates) => 
+					user.info(format!("{}\r\n", candidates.join("  ")).as_bytes()),
+				Completion::Nothing => (),
+			},
+
+			[3] => {
+				let ctrl_c = user.config.lock().unwrap().ctrl_c.unwrap_or(CONFIG.ctrl_c);
+				match ctrl_c {
+					CtrlC::Quit  => Self::close(session, channel, &mut user),
+					CtrlC::Clear => {
+						user.buf_clear();
+						user.redraw();
+					},
+				}
+			},
+
+			// like a shell, only leaves on an empty line. otherwise deletes under the cursor
+			[4] if user.buffer.is_empty() => Self::close(session, channel, &mut user),
+			[4] => {
+				if user.cursor == user.buffer.len() { return Ok(()); }
+
+				let (bytes, cols) = user.char_at();
+				let cursor = user.cursor;
+				user.buffer.drain(cursor..cursor + bytes);
+
+				data!(format!("\x1b[{cols}P"));
+			},
+
+			[13] => {
+				if user.buffer.is_empty() { return Ok(()); }
+
+				// move the buffer out so command can borrow the rest of user, 
+				// it gets handed back (cleared) once its done to keep the allocation around
+				let mut buffer = mem::take(&mut user.buffer);
+				if let Some(cmd) = buffer.trim_ascii().strip_prefix(b":") {
+					if let Err(e) = Self::command(channel, session, cmd, &mut user) {
+						user.info(e.to_string().as_bytes());
+					};
+
+					buffer.clear();
+					user.buffer = buffer;
+					user.buf_clear();
+					if matches!(user.state, UserState::Normal) { user.redraw(); }
+					return Ok(());
+				}
+				user.buffer = buffer;
+
+				if !user.perms().can_write() {
+					user.info(commands::CommandError::Forbidden.to_string().as_bytes());
+					user.buf_clear();
+					return Ok(());
+				}
+
+				let msg = Arc::<str>::from(user.config.lock().unwrap().expand(&String::from_utf8_lossy(&user.buffer)));
+				user.channel.send(Event::msg(user.name.clone(), Arc::clone(&msg))).unwrap();
+
+				user.buf_clear();
+				user.redraw();
+				commands::after_send(&mut user, &msg, None);
+			},
+
+			[127] => { // backsapce
+				if user.cursor == 0 { return Ok(()); }
+
+				let (bytes, cols) = user.char_before();
+				let cursor = user.cursor;
+				user.buffer.drain(cursor - bytes..cursor);
+				user.cursor -= bytes;
+
+				data!(format!("\x1b[{cols}D\x1b[{cols}P"));
+			},
+
+			[27, 91, 65] | // up arrow //TODO: replies
+			[27, 91, 66]   // down arrow
+				=> (),
+
+			[27, 91, 67] => { // right arrow
+				if user.cursor == user.buffer.len() { return Ok(()); }
+				let (bytes, cols) = user.char_at();
+				user.cursor += bytes;
+				data!(format!("\x1b[{cols}C"));
+			},
+
+			[27, 91, 68] => { // left arrow
+				if user.cursor == 0 { return Ok(()); }
+				let (bytes, cols) = user.char_before();
+				user.cursor -= bytes;
+				data!(format!("\x1b[{cols}D"));
+			},
+
+			_ => {
+				const MAX_MSG_LEN: usize = 1024;
+				if user.buffer.len() >= MAX_MSG_LEN { return Ok(()); }
+
+				let cursor = user.cursor;
+				user.buffer.splice(cursor..cursor, data.iter().cloned());
+				user.cursor += data.len();
+
+				data!(data);
+				user.typed();
+			},
+		} 
+		Ok(())
+	}
+}
@@ -0,0 +1,177 @@
+// *bold*, _italic_ and `code`, nothing fancier. anything unmatched is left as typed.
+// links get picked out first so their underscores and such dont turn into formatting
+use std::borrow::Cow;
+
+use crate::event::colour::*;
+
+const MARKERS: [char; 4] = ['*', '_', '`', '\\'];
+
+// whats inside a ``` block, which is how pastes get sent. only ever the whole message
+pub fn fenced(msg: &str) -> Option<&str>
+	{ msg.strip_prefix("```\n")?.strip_suffix("\n```") }
+
+pub fn render(msg: &str) -> Cow<'_, str> {
+	if !msg.contains(MARKERS) && !msg.contains("://") { return Cow::Borrowed(msg); }
+
+	let chars = msg.chars().collect::<Vec<_>>();
+	let mut out = String::with_capacity(msg.len() + 16);
+	let (mut bold, mut italic) = (false, false);
+
+	// a reset takes everything off, so put back whatever is still open
+	let restore = |out: &mut String, bold: bool, italic: bool| {
+		*out += RESET;
+		if bold   { *out += BOLD; }
+		if italic { *out += ITALIC; }
+	};
+
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		if let Some(end) = url_end(&chars, i) {
+			// osc 8 makes it clickable where thats supported, the rest just see it underlined
+			let url = chars[i..end].iter().collect::<String>();
+			out += &format!("\x1b]8;;{url}\x1b\\{UNDERLINE}{BLUE}{url}\x1b]8;;\x1b\\");
+			restore(&mut out, bold, italic);
+			i = end;
+			continue;
+		}
+
+		match c {
+			'\\' if chars.get(i + 1).is_some_and(|next| MARKERS.contains(next)) => {
+				out.push(chars[i + 1]);
+				i += 2;
+				continue;
+			},
+			'`' => if let Some(end) = closer(&chars, i) {
+				out += REVERSE;
+				out.extend(&chars[i + 1..end]);
+				restore(&mut out, bold, italic);
+				i = end + 1;
+				continue;
+			},
+			'*' | '_' => {
+				let open = if c == '*' { bold } else { italic };
+				let toggle = match open {
+					true  => closes(&chars, i),
+					false => opens(&chars, i) && closer(&chars, i).is_some(),
+				};
+				if toggle {
+					match c { '*' => bold = !bold, _ => italic = !italic }
+					match open {
+						true  => restore(&mut out, bold, italic),
+						false => out += if c == '*' { BOLD } else { ITALIC },
+					}
+					i += 1;
+					continue;
+				}
+			},
+			_ => (),
+		}
+		out.push(c);
+		i += 1;
+	}
+
+	if bold || italic { out += RESET; } // crossed over, like *a _b* c_ with the c_ missing
+	Cow::Owned(out)
+}
+
+fn escaped(chars: &[char], i: usize) -> bool
+	{ i > 0 && chars[i - 1] == '\\' }
+
+// word boundaries, so snake_case and 2*3*4 are left alone
+fn opens(chars: &[char], i: usize) -> bool {
+	!escaped(chars, i)
+		&& (i == 0 || !chars[i - 1].is_alphanumeric())
+		&& chars.get(i + 1).is_some_and(|c| !c.is_whitespace())
+}
+
+fn closes(chars: &[char], i: usize) -> bool {
+	!escaped(chars, i)
+		&& i > 0 && !chars[i - 1].is_whitespace()
+		&& chars.get(i + 1).is_none_or(|c| !c.is_alphanumeric())
+}
+
+// where the marker at i gets closed, if it does at all
+fn closer(chars: &[char], i: usize) -> Option<usize> {
+	let marker = chars[i];
+	(i + 2..chars.len()).find(|&j| chars[j] == marker && match marker {
+		'`' => !escaped(chars, j),
+		_   => closes(chars, j),
+	})
+}
+
+// one past the end of the link starting at i, if there is one
+fn url_end(chars: &[char], i: usize) -> Option<usize> {
+	if i > 0 && chars[i - 1].is_alphanumeric() { return None; }
+	let rest = || chars[i..].iter();
+	let scheme = ["https://", "http://"].into_iter()
+		.find(|scheme| rest().take(scheme.len()).copied().eq(scheme.chars()))?;
+
+	// printable ascii only, so nothing in it can end the osc 8 early
+	let start = i + scheme.len();
+	let mut end = (start..chars.len())
+		.find(|&j| !chars[j].is_ascii_graphic() || matches!(chars[j], '<' | '>' | '"' | '`' | '\\'))
+		.unwrap_or(chars.len());
+
+	// sentence punctuation, and the closing paren when the link itself was in parens
+	while end > start {
+		let open = |o: char| chars[start..end].iter().filter(|&&c| c == o).count();
+		match chars[end - 1] {
+			'.' | ',' | ';' | ':' | '!' | '?' | '\'' | '*' | '_' => end -= 1,
+			')' if open(')') > open('(') => end -= 1,
+			']' if open(']') > open('[') => end -= 1,
+			_ => break,
+		}
+	}
+	(end > start).then_some(end)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// the first link in msg, as url_end sees it
+	fn link(msg: &str) -> Option<String> {
+		let chars = msg.chars().collect::<Vec<_>>();
+		(0..chars.len()).find_map(|i| url_end(&chars, i).map(|end| chars[i..end].iter().collect()))
+	}
+
+	#[test]
+	fn trailing_punctuation() {
+		assert_eq!(link("see https://example.com.").as_deref(), Some("https://example.com"));
+		assert_eq!(link("https://example.com/a?b=c!").as_deref(), Some("https://example.com/a?b=c"));
+		assert_eq!(link("is it https://example.com/x?...").as_deref(), Some("https://example.com/x"));
+		assert_eq!(link("'https://example.com/it's'").as_deref(), Some("https://example.com/it's"));
+		assert_eq!(link("*https://example.com/bold*").as_deref(), Some("https://example.com/bold"));
+	}
+
+	#[test]
+	fn parens() {
+		// balanced ones are part of it
+		assert_eq!(link("https://en.wikipedia.org/wiki/Rust_(language)").as_deref(), Some("https://en.wikipedia.org/wiki/Rust_(language)"));
+		assert_eq!(link("(https://en.wikipedia.org/wiki/Rust_(language))").as_deref(), Some("https://en.wikipedia.org/wiki/Rust_(language)"));
+		// the one closing around it isnt
+		assert_eq!(link("(see https://example.com)").as_deref(), Some("https://example.com"));
+		assert_eq!(link("[https://example.com/a].").as_deref(), Some("https://example.com/a"));
+		// an open one left alone
+		assert_eq!(link("https://example.com/(x").as_deref(), Some("https://example.com/(x"));
+	}
+
+	#[test]
+	fn end_of_input() {
+		assert_eq!(link("go to https://example.com").as_deref(), Some("https://example.com"));
+		assert_eq!(link("http://a").as_deref(), Some("http://a"));
+		assert_eq!(link("just https://"), None);
+		assert_eq!(link("https://..."), None);
+	}
+
+	#[test]
+	fn non_ascii() {
+		assert_eq!(link("見て https://example.com/ です").as_deref(), Some("https://example.com/"));
+		assert_eq!(link("https://example.com/café").as_deref(), Some("https://example.com/caf"));
+		assert_eq!(link("https://例え.jp"), None);
+		// glued to a word, in any script
+		assert_eq!(link("xhttps://example.com"), None);
+		assert_eq!(link("本https://example.com"), None);
+	}
+}
@@ -0,0 +1,78 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::fmt::Write;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use log::{info, warn, debug};
+
+use crate::channel::Channel;
+use crate::SERVER;
+
+pub static MESSAGES:      AtomicU64 = AtomicU64::new(0);
+pub static AUTH_FAILURES: AtomicU64 = AtomicU64::new(0);
+pub static BYTES_SENT:    AtomicU64 = AtomicU64::new(0);
+pub static CONNECTIONS:   AtomicU64 = AtomicU64::new(0); // goes down too, see ChatClient's Drop and ws::Counted
+
+pub fn inc(counter: &AtomicU64) 
+	{ counter.fetch_add(1, Ordering::Relaxed); }
+
+pub fn sent(bytes: usize) 
+	{ BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed); }
+
+// bare bones http, just enough to hand prometheus its text format at /metrics
+pub async fn serve(addr: SocketAddr) {
+	let listener = match TcpListener::bind(addr).await {
+		Ok(listener) => listener,
+		Err(e) => return warn!("Error listening for metrics on {addr}: {e}"),
+	};
+	info!("serving metrics on {addr}");
+
+	loop {
+		match listener.accept().await {
+			Ok((stream, _)) => { tokio::spawn(respond(stream)); },
+			Err(e) => debug!("Error accepting metrics connection: {e}"),
+		}
+	}
+}
+
+async fn respond(mut stream: TcpStream) {
+	// dont care about the request, just wait for the client to finish sending it
+	let mut buf = [0; 1024];
+	let mut len = 0;
+	while !buf[..len].ends_with(b"\r\n\r\n") && len < buf.len() {
+		match stream.read(&mut buf[len..]).await {
+			Ok(0) | Err(_) => return,
+			Ok(n) => len += n,
+		}
+	}
+
+	let (status, body) = match buf[..len].starts_with(b"GET /metrics ") {
+		true  => ("200 OK", render()),
+		false => ("404 Not Found", String::new()),
+	};
+
+	let _ = stream.write_all(format!("HTTP/1.1 {status}\r\n\
+		Content-Type: text/plain; version=0.0.4\r\n\
+		Content-Length: {}\r\n\
+		Connection: close\r\n\r\n{body}", body.len()).as_bytes()).await;
+}
+
+fn render() -> String {
+	fn count(channel: &Arc<RwLock<Channel>>) -> usize {
+		1 + channel.read().unwrap().children.values().map(count).sum::<usize>()
+	}
+
+	let mut out = String::new();
+	let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+		let _ = write!(out, "# HELP crussh_{name} {help}\n# TYPE crussh_{name} {kind}\ncrussh_{name} {value}\n");
+	};
+
+	metric("online_users", "gauge", "Users with at least one open session.", SERVER.online().len() as u64);
+	metric("connections", "gauge", "Open SSH and websocket connections, logged in or not.", CONNECTIONS.load(Ordering::Relaxed));
+	metric("channels", "gauge", "Channels in the tree, root included.", count(&SERVER.read().root_channel) as u64);
+	metric("messages_total", "counter", "Messages broadcast.", MESSAGES.load(Ordering::Relaxed));
+	metric("auth_failures_total", "counter", "Rejected logins.", AUTH_FAILURES.load(Ordering::Relaxed));
+	metric("sent_bytes_total", "counter", "Bytes sent to clients.", BYTES_SENT.load(Ordering::Relaxed));
+	out
+}
@@ -1,19 +1,43 @@
-use std::sync::{Arc, Mutex, RwLock};
-use std::collections::{HashMap, BTreeSet};
-use std::path::Path;
+use std::sync::{Arc, Weak, Mutex, RwLock};
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex as AsyncMutex;
 use tokio::fs::File;
-use std::io::Read;
+use russh::keys::PublicKey;
+use log::{info, debug, warn, error};
 
-use crate::channel::{Channel, PermLevel};
-use crate::user::UserConfig;
+use crate::channel::{Channel, PermLevel, RestrictionKind};
+use crate::user::{UserConfig, Connection};
+use crate::bot::Bot;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct Server {
    pub root_channel: Arc<RwLock<Channel>>, // double indirection cause needs to be shared
    pub users:        HashMap<Arc<str>, Arc<Mutex<UserConfig>>>,
+   #[serde(default)]
+   last_channel_id:  AtomicU64, // root is 0
    #[serde(skip)]
-   pub online_users: BTreeSet<Arc<str>>,
+   path_cache:       Mutex<PathCache>,
+}
+
+// channel_from_path results, wiped whenever the tree changes shape
+#[derive(Default)]
+struct PathCache {
+	generation: u64, // bumped on every wipe, so lookups racing one dont cache stale results
+	paths:      HashMap<PathBuf, Weak<RwLock<Channel>>>, // only ones that exist, so made up paths cant grow it
+}
+
+// way more channels than anyone has, just so its never unbounded
+const PATH_CACHE_MAX: usize = 4096;
+
+impl PathCache {
+	fn insert(&mut self, path: &Path, channel: &Arc<RwLock<Channel>>) {
+		if self.paths.len() >= PATH_CACHE_MAX { self.paths.retain(|_, c| c.strong_count() > 0); }
+		if self.paths.len() >= PATH_CACHE_MAX { self.paths.clear(); }
+		self.paths.insert(PathBuf::from(path), Arc::downgrade(channel));
+	}
 }
 
 impl Default for Server {
@@ -21,19 +45,34 @@ impl Default for Server {
 		let mut users = HashMap::new();
       users.insert(Arc::from("admin"), Arc::new(Mutex::new(crate::user::UserConfig {
 			hash:  0xd8acbb0fa6cac9, // "admin"
-			roles: vec![(Box::from("admin"), PermLevel::READ|PermLevel::WRITE|PermLevel::MANAGE)],
+			roles: vec![(Box::from("admin"), PermLevel::READ|PermLevel::WRITE|PermLevel::MANAGE|PermLevel::ADMIN)],
 			.. Default::default()
 		})));
 
+      let mut root = Channel::new(0);
+      root.perms.push((RestrictionKind::All, PermLevel::READ|PermLevel::WRITE));
+
       Self {
          users,
-         root_channel: Arc::new(RwLock::new(Channel::new())),
-         online_users: BTreeSet::new(),
+         root_channel: Arc::new(RwLock::new(root)),
+         last_channel_id: AtomicU64::new(0),
+         path_cache:   Mutex::default(),
       }
    }
 }
 
+const NAME_LEN: std::ops::RangeInclusive<usize> = 2..=24;
+const RESERVED_NAMES: &[&str] = &["all", "everyone", "here", "server", "system"];
+
 impl Server {
+	// names end up in paths, perms, and rendered messages, so keep them boring
+	pub fn valid_name(name: &str) -> bool {
+		NAME_LEN.contains(&name.len())
+			&& name.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+			&& !RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(name))
+			&& !crate::CONFIG.guest_name.eq_ignore_ascii_case(name) // even with guests off, for when they get turned on
+	}
+
    pub fn validate_pass(&self, uname: &str, pass: &str) -> Option<Arc<Mutex<UserConfig>>> {
       let user = self.users.get(&Arc::from(uname))?;
 
@@ -42,73 +81,708 @@ impl Server {
          .then(|| Arc::clone(user)); u
    }
 
+	pub fn validate_key(&self, uname: &str, key: &PublicKey) -> Option<Arc<Mutex<UserConfig>>> {
+		let user = self.users.get(&Arc::from(uname))?;
+		let known = user.lock().unwrap().keys.iter()
+			.any(|k| PublicKey::from_openssh(k).is_ok_and(|k| k.key_data() == key.key_data()));
+		known.then(|| Arc::clone(user))
+	}
+
+	pub fn new_channel_id(&self) -> u64 
+		{ self.last_channel_id.fetch_add(1, Ordering::Relaxed) + 1 }
+
+	// perms used to go unchecked, so older (json) states left root open by having nothing on it
+	fn fix_root_perms(&mut self) {
+		let mut root = self.root_channel.write().unwrap();
+		if root.perms.is_empty() { root.perms.push((RestrictionKind::All, PermLevel::READ|PermLevel::WRITE)); }
+	}
+
+	// global MANAGE used to mean admin, so (json) states from before ADMIN (where nobody has it) keep their admins
+	fn fix_admin_roles(&mut self) {
+		let roles = || self.users.values().flat_map(|c| c.lock().unwrap().roles.clone());
+		if roles().any(|(_, level)| level.can_admin()) { return; }
+
+		for config in self.users.values() {
+			for (_, level) in &mut config.lock().unwrap().roles
+				{ if level.can_manage() { *level |= PermLevel::ADMIN; } }
+		}
+	}
+
+	// older (json) states predate channel ids and hand edited ones might clash, so hand out fresh ones
+	fn fix_channel_ids(&mut self) {
+		let mut max = 0;
+		Channel::for_each_mut(&self.root_channel, &mut |c| max = max.max(c.id));
+		self.last_channel_id.fetch_max(max, Ordering::Relaxed);
+
+		let mut seen = HashSet::new();
+		Channel::for_each_mut(&self.root_channel, &mut |c| 
+			if !seen.insert(c.id) { 
+				c.id = self.last_channel_id.fetch_add(1, Ordering::Relaxed) + 1;
+				seen.insert(c.id);
+			});
+	}
+
+	// channel_from_path stops at the closest parent, this only takes the channel at exactly `path`
+	pub fn channel_at(&self, path: &Path) -> Option<Arc<RwLock<Channel>>> {
+		let channel = self.channel_from_path(path)?;
+		let id = channel.read().unwrap().id;
+		self.channel_from_id(id).filter(|(found, _)| found == path).map(|(_, c)| c)
+	}
+
+	// along with where it currently lives in the tree
+	pub fn channel_from_id(&self, id: u64) -> Option<(PathBuf, Arc<RwLock<Channel>>)> {
+		fn find(path: PathBuf, channel: &Arc<RwLock<Channel>>, id: u64) 
+		-> Option<(PathBuf, Arc<RwLock<Channel>>)> {
+			let c = channel.read().unwrap();
+			if c.id == id { return Some((path, Arc::clone(channel))); }
+			c.children.iter().find_map(|(name, c)| find(path.join(&**name), c, id))
+		}
+
+		find(PathBuf::from("/"), &self.root_channel, id)
+	}
+
+	// / down to what channel_from_path gives for path, so the same closest parent when it doesnt exist
+	pub fn lineage(&self, path: &Path) -> Vec<Arc<RwLock<Channel>>> {
+		let mut lineage = vec![Arc::clone(&self.root_channel)];
+		for name in path.strip_prefix("/").into_iter().flat_map(Path::iter) {
+			let child = name.to_str().and_then(|name| 
+				lineage.last().unwrap().read().unwrap().children.get(name).cloned());
+			match child {
+				Some(child) => lineage.push(child),
+				None => break,
+			}
+		}
+		lineage
+	}
+
+	// what they get in the channel at path, inherited perms and all
+	pub fn perms_at(&self, path: &Path, name: &str, config: &UserConfig) -> PermLevel
+		{ Channel::perms_along(&self.lineage(path), name, config) }
+
+	// call *after* adding, removing, or moving a channel
+	pub fn invalidate_paths(&mut self) {
+		let mut cache = self.path_cache.lock().unwrap();
+		cache.generation += 1;
+		cache.paths.clear();
+	}
+
 	pub fn channel_from_path(&self, path: &Path) -> Option<Arc<RwLock<Channel>>> {
-		fn channel_from_path(
-			channels: &HashMap<Box<str>, Arc<RwLock<Channel>>>,
-			mut path: std::path::Iter<'_>) 
-		-> Option<Arc<RwLock<Channel>>> {
-			let channel = channels.get(path.next()?.to_str()?)?;
-			channel_from_path(&channel.read().unwrap().children, path)
-				.unwrap_or_else(|| Arc::clone(channel))
-				.into()
+		let generation = {
+			let mut cache = self.path_cache.lock().unwrap();
+			match cache.paths.get(path).map(Weak::upgrade) {
+				Some(Some(channel)) => return Some(channel),
+				Some(None) => { cache.paths.remove(path); }, // went away without anyone invalidating
+				None => (),
+			}
+			cache.generation
+		};
+
+		let (channel, exact) = Self::walk_path(&self.root_channel, path)?;
+
+		let mut cache = self.path_cache.lock().unwrap();
+		if exact && cache.generation == generation { cache.insert(path, &channel); }
+
+		Some(channel)
+	}
+
+	// the closest channel to path, and whether it was path itself
+	fn walk_path(root: &Arc<RwLock<Channel>>, path: &Path) -> Option<(Arc<RwLock<Channel>>, bool)> {
+		let mut channel = Arc::clone(root);
+		for name in path.strip_prefix("/").ok()?.iter() {
+			let child = name.to_str().and_then(|name| channel.read().unwrap().children.get(name).cloned());
+			match child {
+				Some(child) => channel = child,
+				None => return Some((channel, false)),
+			}
 		}
+		Some((channel, true))
+	}
+}
+
+#[derive(Clone, Copy)]
+pub enum StateFormat {
+	Bincode,
+	Json, // for poking at the state by hand
+}
 
-		channel_from_path(
-			&self.root_channel.read().unwrap().children,
-			path.strip_prefix("/").ok()?.iter())
-				.unwrap_or_else(|| Arc::clone(&self.root_channel))
-				.into()
+impl std::str::FromStr for StateFormat {
+	type Err = &'static str;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"bincode" => Ok(Self::Bincode),
+			"json"    => Ok(Self::Json),
+			_         => Err("expected bincode or json"),
+		}
 	}
 }
 
-pub struct ServerSerializer(AsyncMutex<File>, RwLock<Server>);
+type SerdeError = Box<dyn std::error::Error + Send + Sync>;
 
-impl ServerSerializer {
-	pub fn new(path: &str) -> Self { 
-		let mut file = std::fs::OpenOptions::new()
-			.read(true).write(true).create(true)
-			.truncate(true).open(path)
-			.unwrap_or_else(|e| panic!("Error opening {path}: {e}"));
+// bump whenever what gets saved changes shape, and teach migrate how to get there from the one before.
+// bincode has no idea what a missing field is, so its states only ever load at the version they were saved at
+const STATE_VERSION: u32 = 2;
+const BINCODE_MAGIC: &[u8] = b"crussh\0";
 
-		let mut buf = Vec::with_capacity(file.metadata().unwrap().len() as usize);
-		file.read_to_end(&mut buf)
-			.unwrap_or_else(|e| panic!("Error reading {path}: {e}"));
+// a json state one version up. everything added so far came with a default, so theres nothing to do yet
+fn migrate(version: u32, _state: &mut serde_json::Value) {
+	match version {
+		0 => (), // from before states had versions
+		1 => (), // users got keys
+		_ => unreachable!("no migration from state version {version}"),
+	}
+}
+
+// bincode states from before the header, read the way that build wrote them since bincode has no
+// field names to go by. it never truncated on save either, so the file is every state it saved
+// back to back and the last one that reads in full is the newest. the next save writes the current format
+mod headerless {
+	use std::collections::HashMap;
+	use std::sync::{Arc, Mutex, RwLock};
+	use log::info;
+
+	use crate::channel::{self, PermLevel, RestrictionKind};
+	use crate::user;
+
+	#[derive(serde::Deserialize)]
+	struct Server {
+		root_channel: Channel,
+		users:        HashMap<Arc<str>, UserConfig>,
+	}
+
+	#[derive(serde::Deserialize)]
+	struct Channel {
+		perms:    Vec<(RestrictionKind, PermLevel)>,
+		children: HashMap<Box<str>, Channel>,
+	}
+
+	#[derive(serde::Deserialize)]
+	struct UserConfig {
+		hash:        u64, // plain, not hex yet
+		roles:       Vec<(Box<str>, PermLevel)>,
+		last_login:  u64,
+		online_time: u64,
+	}
+
+	pub fn load(mut buf: &[u8]) -> Result<super::Server, super::SerdeError> {
+		let mut newest = None;
+		while !buf.is_empty() {
+			match bincode::deserialize_from::<_, Server>(&mut buf) {
+				Ok(server) => newest = Some(server),
+				Err(_) => break, // cut off mid save
+			}
+		}
+		let old = newest.ok_or("its not a bincode state this build knows how to read")?;
+		info!("read a bincode state from before state versions, itll be saved in the current format from now on");
+
+		Ok(super::Server {
+			root_channel: Arc::new(RwLock::new(old.root_channel.into())),
+			users: old.users.into_iter().map(|(name, c)| (name, Arc::new(Mutex::new(c.into())))).collect(),
+			..Default::default()
+		})
+	}
+
+	impl From<Channel> for channel::Channel {
+		fn from(old: Channel) -> Self {
+			let mut channel = channel::Channel::new(0); // fix_channel_ids hands out real ones
+			channel.perms = old.perms;
+			channel.perms.sort_unstable_by(|a, b| a.0.cmp(&b.0)); // like perms_sorted
+			channel.children = old.children.into_iter().map(|(name, c)| (name, Arc::new(RwLock::new(c.into())))).collect();
+			channel
+		}
+	}
+
+	impl From<UserConfig> for user::UserConfig {
+		fn from(old: UserConfig) -> Self {
+			Self { hash: old.hash, roles: old.roles, last_login: old.last_login, online_time: old.online_time, ..Default::default() }
+		}
+	}
+}
+
+impl StateFormat {
+	// bincode gets a header in front, json a "version" next to everything else
+	fn serialize(self, server: &Server) -> Result<Vec<u8>, SerdeError> {
+		Ok(match self {
+			Self::Bincode => {
+				let mut buf = [BINCODE_MAGIC, &STATE_VERSION.to_le_bytes()].concat();
+				bincode::serialize_into(&mut buf, server)?;
+				buf
+			},
+			Self::Json => {
+				let mut state = serde_json::to_value(server)?;
+				state["version"] = STATE_VERSION.into();
+				serde_json::to_vec_pretty(&state)?
+			},
+		})
+	}
+
+	fn deserialize(self, buf: &[u8]) -> Result<Server, SerdeError> {
+		match self {
+			Self::Bincode => {
+				let Some(rest) = buf.strip_prefix(BINCODE_MAGIC) else { return headerless::load(buf); };
+				let (version, rest) = rest.split_first_chunk::<4>().ok_or("its cut off")?;
+				let version = u32::from_le_bytes(*version);
+				if version != STATE_VERSION {
+					Err(format!("its a version {version} bincode state, this build only reads version {STATE_VERSION} ones (see STATE_FORMAT in the README)"))?;
+				}
+				Ok(bincode::deserialize(rest)?)
+			},
+			Self::Json => {
+				let mut state: serde_json::Value = serde_json::from_slice(buf)?;
+				let version = state.get("version").and_then(serde_json::Value::as_u64).unwrap_or(0);
+				let version = u32::try_from(version).ok().filter(|&v| v <= STATE_VERSION)
+					.ok_or_else(|| format!("its a version {version} state, newer than this build knows ({STATE_VERSION})"))?;
+
+				for version in version..STATE_VERSION { migrate(version, &mut state); }
+				Ok(serde_json::from_value(state)?)
+			},
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Backups {
+	pub dir:      Option<PathBuf>, // next to the state file if unset
+	pub keep:     usize,
+	pub interval: Duration,
+}
+
+struct StateFile {
+	path:        PathBuf,
+	written:     u64, // generation that last made it to disk
+	last_backup: Option<Instant>,
+}
+
+// presence spans every session of a name, so it lives here rather than on User
+#[derive(Default)]
+pub struct Presence {
+	pub conns: Vec<Arc<Connection>>, // one per open session
+	pub ws:    usize,                // websocket sessions, theres no Connection to keep for those
+	pub away:  Option<Arc<str>>,     // empty when they didnt leave a message
+}
+
+impl Presence {
+	pub fn sessions(&self) -> usize
+		{ self.conns.len() + self.ws }
+}
+
+pub type OnlineUsers = BTreeMap<Arc<str>, Presence>;
+
+pub struct ServerSerializer {
+	file:       AsyncMutex<StateFile>,
+	server:     RwLock<Server>,
+	// presence changes on every join/leave, keep it from fighting over (and persisting) the rest.
+	// take it after the server lock if you need both, never the other way around
+	online:     RwLock<OnlineUsers>,
+	bots:       RwLock<Vec<Arc<dyn Bot>>>,
+	generation: AtomicU64,
+	format:     StateFormat,
+	backups:    Option<Backups>,
+}
 
-		Self(AsyncMutex::new(File::from_std(file)),
-			RwLock::new(bincode::deserialize(&buf)
-				.unwrap_or_default()))
+impl ServerSerializer {
+	pub fn new(path: &Path, format: StateFormat, backups: Option<Backups>) -> Self { 
+		let mut server = match std::fs::read(path) {
+			Ok(buf) if buf.is_empty() => Server::default(),
+			// refuse to start rather than overwrite a state we couldnt make sense of
+			Ok(buf) => format.deserialize(&buf)
+				.unwrap_or_else(|e| panic!("Error parsing {}: {e}", path.display())),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				info!("no state file at {}, starting fresh", path.display());
+				Server::default()
+			},
+			Err(e) => panic!("Error reading {}: {e}", path.display()),
+		};
+		server.fix_channel_ids();
+		server.fix_root_perms();
+		server.fix_admin_roles();
+
+		Self {
+			file: AsyncMutex::new(StateFile {
+				path:        path.to_path_buf(),
+				written:     0,
+				last_backup: None,
+			}),
+			server:     RwLock::new(server),
+			online:     RwLock::default(),
+			bots:       RwLock::default(),
+			generation: AtomicU64::new(0),
+			format, backups,
+		}
 	}
 
 	pub fn read(&self) -> std::sync::RwLockReadGuard<'_, Server>
-	{ self.1.read().unwrap() }
+	{ self.server.read().unwrap() }
+
+	pub fn write(&self) -> GuardMut<'_>
+	{ GuardMut { server: self.server.write().unwrap(), dirty: false } }
+
+	// moves on every change that gets saved, so anything derived from the state can tell its stale
+	pub fn generation(&self) -> u64
+	{ self.generation.load(Ordering::Relaxed) }
 
-	pub fn write(&self) -> GuardMut
-	{ GuardMut(self.1.write().unwrap()) }
+	pub fn online(&self) -> std::sync::RwLockReadGuard<'_, OnlineUsers>
+	{ self.online.read().unwrap() }
+
+	pub fn online_mut(&self) -> std::sync::RwLockWriteGuard<'_, OnlineUsers>
+	{ self.online.write().unwrap() }
+
+	pub fn register_bot(&self, bot: Arc<dyn Bot>)
+	{ self.bots.write().unwrap().push(bot); }
+
+	// cloned out so bots run without holding anything
+	pub fn bots(&self) -> Vec<Arc<dyn Bot>>
+	{ self.bots.read().unwrap().clone() }
+
+	// false if they already have max sessions open, and then nothing changes
+	pub fn go_online(&self, name: &Arc<str>, conn: &Arc<Connection>, max: Option<usize>) -> bool {
+		let mut online = self.online_mut();
+		let presence = online.entry(Arc::clone(name)).or_default();
+		if max.is_some_and(|max| presence.sessions() >= max) { return false; }
+
+		presence.conns.push(Arc::clone(conn));
+		true
+	}
+
+	// same as go_online, for a websocket session
+	#[cfg(feature = "websocket")]
+	pub fn ws_online(&self, name: &Arc<str>, max: Option<usize>) -> bool {
+		let mut online = self.online_mut();
+		let presence = online.entry(Arc::clone(name)).or_default();
+		if max.is_some_and(|max| presence.sessions() >= max) { return false; }
+
+		presence.ws += 1;
+		true
+	}
+
+	#[cfg(feature = "websocket")]
+	pub fn ws_offline(&self, name: &str) {
+		let mut online = self.online_mut();
+		let Some(presence) = online.get_mut(name) else { return; };
+		presence.ws = presence.ws.saturating_sub(1);
+		if presence.sessions() == 0 { online.remove(name); }
+	}
+
+	// only drops this session, the name stays online while any others are open
+	pub fn go_offline(&self, name: &str, conn: &Arc<Connection>) {
+		let mut online = self.online_mut();
+		let Some(presence) = online.get_mut(name) else { return; };
+		presence.conns.retain(|c| !Arc::ptr_eq(c, conn));
+		if presence.sessions() == 0 { online.remove(name); }
+	}
+
+	pub fn away(&self, name: &str) -> Option<Arc<str>>
+	{ self.online().get(name).and_then(|p| p.away.clone()) }
+
+	// true if that changed anything
+	pub fn set_away(&self, name: &str, away: Option<Arc<str>>) -> bool {
+		let mut online = self.online_mut();
+		let Some(presence) = online.get_mut(name) else { return false; };
+		let changed = presence.away.is_some() || away.is_some();
+		presence.away = away;
+		changed
+	}
+
+	// a full disk or a state that wont serialize shouldnt take the server down, whats in memory is
+	// still fine. keep at it until it makes it to disk or a newer change takes over from us
+	async fn persist(&self, generation: u64, mut buf: Result<Vec<u8>, SerdeError>) {
+		const MAX_WAIT: Duration = Duration::from_secs(60);
+
+		let mut wait = Duration::from_secs(1);
+		loop {
+			let result = match buf {
+				Ok(buf) => self.save(generation, buf, false).await.map_err(SerdeError::from),
+				Err(e)  => Err(e),
+			};
+			let Err(e) = result else { return; };
+
+			let path = self.file.lock().await.path.clone();
+			error!("couldnt save state to {}, trying again in {}s: {e}", path.display(), wait.as_secs());
+			tokio::time::sleep(wait).await;
+			wait = (wait * 2).min(MAX_WAIT);
+
+			// under the lock, so theres no change between checking and serializing
+			let server = self.read();
+			if self.generation() != generation { return; } // that one is saving itself
+			buf = self.format.serialize(&server);
+		}
+	}
+
+	// the whole state every so often, changed or not, in case something forgot to mark it.
+	// goes through save like the rest so it never writes over anything newer
+	pub async fn snapshots(&self, every: Duration) {
+		let mut interval = tokio::time::interval(every);
+		interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+		interval.tick().await; // the first one is right away, theres nothing to catch yet
+
+		loop {
+			interval.tick().await;
+			let (generation, buf) = {
+				let server = self.read();
+				(self.generation(), self.format.serialize(&server))
+			};
+
+			let result = match buf {
+				Ok(buf) => self.save(generation, buf, true).await.map_err(SerdeError::from),
+				Err(e)  => Err(e),
+			};
+			match result {
+				Ok(()) => debug!("snapshotted state at generation {generation}"),
+				Err(e) => error!("couldnt snapshot state, trying again in {}s: {e}", every.as_secs()),
+			}
+		}
+	}
+
+	// write everything to a temp file first and rename it over the old one,
+	// so a crash mid write can never leave a half written state behind.
+	// forced rewrites the generation that made it last time, for snapshots
+	async fn save(&self, generation: u64, buf: Vec<u8>, force: bool) -> std::io::Result<()> {
+		use tokio::io::AsyncWriteExt;
+
+		let mut file = self.file.lock().await;
+		// saves can race each other, dont let an older one clobber a newer one
+		if generation < file.written || (generation == file.written && !force) { return Ok(()); }
+
+		// no backup is no reason to not save at all
+		if let Err(e) = self.backup(&mut file).await { warn!("couldnt back up state: {e}"); }
+
+		let mut tmp = file.path.clone().into_os_string();
+		tmp.push(".tmp");
+
+		let mut f = File::create(&tmp).await?;
+		f.write_all(&buf).await?;
+		f.sync_all().await?;
+		tokio::fs::rename(&tmp, &file.path).await?;
+
+		file.written = generation;
+		Ok(())
+	}
+
+	// copy the current state file to `<name>.<timestamp>`, at most once per interval,
+	// and only keep the newest few of those around
+	async fn backup(&self, file: &mut StateFile) -> std::io::Result<()> {
+		let Some(backups) = &self.backups else { return Ok(()); };
+
+		if file.last_backup.is_some_and(|t| t.elapsed() < backups.interval) { return Ok(()); }
+		if !tokio::fs::try_exists(&file.path).await? { return Ok(()); } // nothing to back up yet
+
+		let dir = backups.dir.as_deref()
+			.or_else(|| file.path.parent())
+			.filter(|d| !d.as_os_str().is_empty())
+			.unwrap_or(Path::new("."));
+		let name = file.path.file_name()
+			.and_then(|n| n.to_str())
+			.unwrap_or("state");
+
+		let backup = dir.join(format!("{name}.{}", chrono::Utc::now().format("%Y%m%d%H%M%S")));
+		tokio::fs::create_dir_all(dir).await?;
+		tokio::fs::copy(&file.path, &backup).await?;
+		file.last_backup = Some(Instant::now());
+		debug!("backed up state to {}", backup.display());
+
+		let mut old = Vec::new();
+		let mut entries = tokio::fs::read_dir(dir).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			if entry.file_name().to_str()
+				.and_then(|n| n.strip_prefix(name)?.strip_prefix('.'))
+				.is_some_and(|stamp| stamp.bytes().all(|b| b.is_ascii_digit()))
+				{ old.push(entry.path()); }
+		}
+
+		// timestamps sort the same as strings
+		old.sort_unstable();
+		for path in old.iter().rev().skip(backups.keep) 
+			{ tokio::fs::remove_file(path).await?; }
+
+		Ok(())
+	}
 }
 
-pub struct GuardMut<'a>(std::sync::RwLockWriteGuard<'a, Server>);
+// only saves if something actually changed, going through deref_mut counts
+pub struct GuardMut<'a> {
+	server: std::sync::RwLockWriteGuard<'a, Server>,
+	dirty:  bool,
+}
+
+impl GuardMut<'_> {
+	// for changes behind the inner locks (user configs, channels), which deref_mut cant see
+	pub fn changed(&mut self)
+	{ self.dirty = true; }
+}
 
 impl std::ops::Deref for GuardMut<'_> {
 	type Target = Server;
 	fn deref(&self) -> &Self::Target 
-	{ &self.0 }
+	{ &self.server }
 }
 
 impl std::ops::DerefMut for GuardMut<'_> {
-	fn deref_mut(&mut self) -> &mut Self::Target
-	{ &mut self.0 }
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.dirty = true;
+		&mut self.server
+	}
 }
 
 impl std::ops::Drop for GuardMut<'_> {
 	fn drop(&mut self) {
-		use tokio::io::AsyncWriteExt;
-		let buf = bincode::serialize(&*self.0)
-			.expect("Error serializing");
-
-		tokio::spawn(async move {
-			crate::SERVER.0.lock().await
-				.write_all(&buf).await
-				.expect("Error writing to file")
-		});
+		if !self.dirty { return; }
+
+		let buf = crate::SERVER.format.serialize(&self.server);
+		// still holding the write lock, so generations follow the order of the changes
+		let generation = crate::SERVER.generation.fetch_add(1, Ordering::Relaxed) + 1;
+
+		tokio::spawn(crate::SERVER.persist(generation, buf));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// a server with a channel at each of paths, parents first
+	fn with_channels(paths: &[&str]) -> Server {
+		let server = Server::default();
+		for path in paths {
+			let (parent, name) = path.rsplit_once('/').unwrap();
+			let parent = server.lineage(Path::new(if parent.is_empty() { "/" } else { parent })).pop().unwrap();
+			let child = Arc::new(RwLock::new(Channel::new(server.new_channel_id())));
+			parent.write().unwrap().children.insert(Box::from(name), child);
+		}
+		server
+	}
+
+	#[test]
+	fn only_real_paths_get_cached() {
+		let server = with_channels(&["/a", "/a/b"]);
+		let b = server.channel_from_path(Path::new("/a/b")).unwrap();
+		for n in 0..100 {
+			let path = format!("/a/b/nope{n}");
+			assert!(Arc::ptr_eq(&server.channel_from_path(Path::new(&path)).unwrap(), &b));
+		}
+		let cache = server.path_cache.lock().unwrap();
+		assert_eq!(cache.paths.keys().collect::<Vec<_>>(), [Path::new("/a/b")]);
+	}
+
+	#[test]
+	fn path_cache_stays_bounded() {
+		let names = (0..PATH_CACHE_MAX + 10).map(|n| format!("/c{n}")).collect::<Vec<_>>();
+		let server = with_channels(&names.iter().map(String::as_str).collect::<Vec<_>>());
+		for name in &names { server.channel_from_path(Path::new(name)).unwrap(); }
+		assert!(server.path_cache.lock().unwrap().paths.len() <= PATH_CACHE_MAX);
+	}
+
+	// a fresh dir per test, so they can run side by side
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("crussh-test-{}-{name}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	// straight to the lock, GuardMut would go save it to the real SERVER
+	fn add_user(state: &ServerSerializer, name: &str)
+		{ state.server.write().unwrap().users.insert(Arc::from(name), Arc::default()); }
+
+	fn users(state: &ServerSerializer) -> Vec<Arc<str>> {
+		let mut users = state.read().users.keys().cloned().collect::<Vec<_>>();
+		users.sort();
+		users
+	}
+
+	#[tokio::test]
+	async fn partial_write_keeps_the_old_state() {
+		let dir = temp_dir("partial-write");
+		let path = dir.join("state.bin");
+		let mut tmp = path.clone().into_os_string();
+		tmp.push(".tmp");
+
+		let state = ServerSerializer::new(&path, StateFormat::Json, None);
+		add_user(&state, "bob");
+		let buf = StateFormat::Json.serialize(&state.read()).unwrap();
+		state.save(1, buf, false).await.unwrap();
+
+		// dies halfway through writing the next one
+		add_user(&state, "carol");
+		let buf = StateFormat::Json.serialize(&state.read()).unwrap();
+		std::fs::write(&tmp, &buf[..buf.len() / 2]).unwrap();
+		assert_eq!(users(&ServerSerializer::new(&path, StateFormat::Json, None)), [Arc::from("admin"), Arc::from("bob")]);
+
+		// and the leftover doesnt get in the way of the next save
+		state.save(2, buf, false).await.unwrap();
+		assert_eq!(users(&ServerSerializer::new(&path, StateFormat::Json, None)), [Arc::from("admin"), Arc::from("bob"), Arc::from("carol")]);
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn failed_write_keeps_the_old_state() {
+		let dir = temp_dir("failed-write");
+		let path = dir.join("state.bin");
+		let mut tmp = path.clone().into_os_string();
+		tmp.push(".tmp");
+
+		let state = ServerSerializer::new(&path, StateFormat::Json, None);
+		let buf = StateFormat::Json.serialize(&state.read()).unwrap();
+		state.save(1, buf, false).await.unwrap();
+
+		// something in the way of the temp file, so the write cant even start
+		std::fs::create_dir(&tmp).unwrap();
+		add_user(&state, "bob");
+		let buf = StateFormat::Json.serialize(&state.read()).unwrap();
+		assert!(state.save(2, buf, false).await.is_err());
+		assert_eq!(state.file.lock().await.written, 1);
+		assert_eq!(users(&ServerSerializer::new(&path, StateFormat::Json, None)), [Arc::from("admin")]);
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	// what the build before state versions left in state.bin after mkch a and mkch a/c: two saves back to back
+	const HEADERLESS: &str = "000000000000000000000000000000000100000000000000050000000000000061646d696ec9caa60fbbacd8000100000000000000050000000000000061646d696e0700000000000000003390cf6a00000000000000000000000001000000000000000100000000000000610200000000000000020000000300000000050000000000000061646d696e0701000000000000000100000000000000630200000000000000020000000300000000050000000000000061646d696e0700000000000000000100000000000000050000000000000061646d696ec9caa60fbbacd8000100000000000000050000000000000061646d696e0700000000000000003390cf6a00000000";
+
+	fn unhex(hex: &str) -> Vec<u8>
+		{ (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect() }
+
+	#[test]
+	fn headerless_bincode_loads() {
+		let buf = unhex(HEADERLESS);
+		let mut server = StateFormat::Bincode.deserialize(&buf).unwrap();
+		server.fix_channel_ids(); // like ServerSerializer::new does
+
+		let admin = server.users[&Arc::<str>::from("admin")].lock().unwrap().hash;
+		assert_eq!(admin, UserConfig::hash(b"admin"));
+		let a = server.channel_at(Path::new("/a")).unwrap();
+		assert!(server.channel_at(Path::new("/a/c")).is_some());
+		assert_eq!(a.read().unwrap().perms, [
+			(RestrictionKind::User(Arc::from("admin")), PermLevel::READ|PermLevel::WRITE|PermLevel::MANAGE),
+			(RestrictionKind::All, PermLevel::READ|PermLevel::WRITE),
+		]);
+
+		// and comes back out with a header
+		let saved = StateFormat::Bincode.serialize(&server).unwrap();
+		assert!(saved.starts_with(BINCODE_MAGIC));
+		let mut server = StateFormat::Bincode.deserialize(&saved).unwrap();
+		server.fix_channel_ids();
+		assert!(server.channel_at(Path::new("/a/c")).is_some());
+	}
+
+	#[test]
+	fn headerless_bincode_cut_off() {
+		// the second save only half made it, so the first is the newest there is
+		let mut buf = unhex(HEADERLESS);
+		buf.truncate(buf.len() - 40);
+		let server = StateFormat::Bincode.deserialize(&buf).unwrap();
+		assert!(server.root_channel.read().unwrap().children.is_empty());
+		assert!(server.users.contains_key("admin"));
+
+		assert!(StateFormat::Bincode.deserialize(b"nonsense").is_err());
+	}
+
+	#[test]
+	fn dead_entries_get_dropped() {
+		let server = with_channels(&["/a"]);
+		server.channel_from_path(Path::new("/a")).unwrap();
+		server.root_channel.write().unwrap().children.clear(); // no invalidate_paths
+
+		assert!(Arc::ptr_eq(&server.channel_from_path(Path::new("/a")).unwrap(), &server.root_channel));
+		assert!(server.path_cache.lock().unwrap().paths.is_empty());
 	}
 }
@@ -0,0 +1,79 @@
+// rfc 6238 totp, the sha1 / 6 digit / 30s flavour every authenticator app does
+use hmac::{Hmac, Mac};
+use data_encoding::BASE32_NOPAD;
+
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+const SKEW: u64 = 1; // steps either side, for clocks that are a bit off
+
+// base32, which is what goes in the uri and UserConfig
+pub fn gen_secret() -> String {
+	let secret: [u8; 20] = rand::random();
+	BASE32_NOPAD.encode(&secret)
+}
+
+// scan it or paste it, most apps take either
+pub fn uri(name: &str, secret: &str) -> String
+	{ format!("otpauth://totp/crussh:{name}?secret={secret}&issuer=crussh&digits={DIGITS}&period={STEP}") }
+
+pub fn verify(secret: &str, code: &str) -> bool
+	{ verify_at(secret, code, chrono::Utc::now().timestamp() as u64) }
+
+fn verify_at(secret: &str, code: &str, time: u64) -> bool {
+	let Ok(key) = BASE32_NOPAD.decode(secret.as_bytes()) else { return false; };
+	let Ok(code) = code.trim().parse::<u32>() else { return false; };
+
+	let now = time / STEP;
+	(now.saturating_sub(SKEW)..=now + SKEW).any(|counter| hotp(&key, counter) == code)
+}
+
+// rfc 4226
+fn hotp(key: &[u8], counter: u64) -> u32 {
+	let mut mac = Hmac::<sha1::Sha1>::new_from_slice(key).expect("hmac takes any key size");
+	mac.update(&counter.to_be_bytes());
+	let hash = mac.finalize().into_bytes();
+
+	let offset = (hash[19] & 0xf) as usize;
+	let bin = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+	bin % 10u32.pow(DIGITS)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const KEY: &[u8] = b"12345678901234567890";
+
+	#[test]
+	fn rfc4226_vectors() {
+		let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+		for (counter, code) in expected.into_iter().enumerate()
+			{ assert_eq!(hotp(KEY, counter as u64), code, "counter {counter}"); }
+	}
+
+	// the sha1 ones from rfc 6238 appendix b, which are 8 digits, so only the last 6 of each
+	#[test]
+	fn rfc6238_vectors() {
+		let secret = BASE32_NOPAD.encode(KEY);
+		for (time, code) in [(59, "287082"), (1111111109, "081804"), (1111111111, "050471"),
+			(1234567890, "005924"), (2000000000, "279037"), (20000000000, "353130")] {
+			assert_eq!(hotp(KEY, time / STEP), code.parse::<u32>().unwrap(), "time {time}");
+			assert!(verify_at(&secret, code, time), "time {time}");
+		}
+	}
+
+	#[test]
+	fn skew_window() {
+		let secret = BASE32_NOPAD.encode(KEY);
+		let time = 1234567890;
+		let code = hotp(KEY, time / STEP).to_string();
+
+		for off in [-(STEP as i64), 0, STEP as i64]
+			{ assert!(verify_at(&secret, &code, time.saturating_add_signed(off)), "off by {off}"); }
+		for off in [-2 * STEP as i64, 2 * STEP as i64]
+			{ assert!(!verify_at(&secret, &code, time.saturating_add_signed(off)), "off by {off}"); }
+
+		assert!(!verify_at(&secret, "not a code", time));
+		assert!(!verify_at("not base32!", &code, time));
+	}
+}
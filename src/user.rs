@@ -1,16 +1,24 @@
 use std::sync::{Arc, Weak, Mutex};
-use std::mem::ManuallyDrop;
-use std::path::{PathBuf, Path};
+use std::path::PathBuf;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tokio::task::{self, JoinHandle};
 use tokio::sync::Mutex as AsyncMutex;
-use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::mpsc::{self, Sender, error::TrySendError};
+use tokio::sync::Notify;
+use std::future::Future;
+use tokio::sync::broadcast::{Receiver, error::RecvError};
 
 use serde::Deserialize;
+use unicode_width::UnicodeWidthChar;
 use russh::CryptoVec;
+use log::{debug, warn};
 
-use crate::channel::{SubscribedChannel, PermLevel};
-use crate::event::Event;
-use crate::SERVER;
+use crate::channel::{Channel, SubscribedChannel, PermLevel};
+use crate::commands::CommandError;
+use crate::event::{Event, MsgId};
+use crate::event::colour::*;
+use crate::{SERVER, CONFIG};
 
 
 pub struct User {
@@ -18,11 +26,21 @@ pub struct User {
    pub buffer:  Vec<u8>,
    pub cursor:  usize,
    pub state:   UserState,
+   pub size:    Option<(u32, u32)>, // columns and rows, if their client said
+   pub ping:    Option<Instant>, // when ping asked their terminal where the cursor is
 
    pub config:  UserConfLock,
-   conn:        Connection,
+   pub conn:    Arc<Connection>,
 
    handle:      JoinHandle<()>,
+   stopped:     bool,
+   idle:        Option<JoinHandle<()>>,
+   last_active: Instant,
+   idle_warned: bool,
+
+   typing:      HashMap<Arc<str>, Instant>, // whos typing here, as of when we last heard
+   typing_sent: Option<Instant>,
+   status:      Option<String>, // whats drawn on the line above the input, if anything
 
    pub channel: SubscribedChannel,
 	pub path:    PathBuf,
@@ -32,15 +50,162 @@ pub struct User {
 pub type UserConfLock = Arc<Mutex<UserConfig>>;
 type Timestamp = u64;
 const PASS_LEN: usize = 8;
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6); // a bit over how often typing gets sent
+pub const PASTE_PROMPT: &str = "\x1b[90m│\x1b[0m "; // in front of every line while pasting, a dim bar
 
 #[derive(Default, Deserialize, serde::Serialize)]
 pub struct UserConfig {
+   #[serde(serialize_with = "UserConfig::serialize_hash")]
    #[serde(deserialize_with = "UserConfig::deserialize_hash")]
    pub hash:  u64,
    pub roles: Vec<(Box<str>, PermLevel)>,
 	
 	pub last_login:  Timestamp,
 	pub online_time: Timestamp,
+
+	// None follows the server default
+	#[serde(default)]
+	pub ctrl_c: Option<CtrlC>,
+
+	#[serde(default)]
+	pub clock: Option<Clock>,
+
+	#[serde(default)]
+	pub tz: Option<Box<str>>, // iana name, utc when unset or unknown
+
+	#[serde(default)]
+	pub no_emoji: bool, // leave :shortcodes: as typed
+
+	#[serde(default)]
+	pub no_markdown: bool, // messages exactly as typed, no formatting or link highlighting
+
+	#[serde(default)]
+	pub bio: Option<Box<str>>, // shown in whois, already stripped of anything that isnt text
+
+	#[serde(default)]
+	pub favorites: Vec<Box<str>>, // full paths
+
+	#[serde(default)]
+	pub last_seen: HashMap<u64, MsgId>, // channel id to the newest message they were around for
+
+	#[serde(default)]
+	pub prompt: Option<Box<str>>, // template, see Prompt. None or broken follows the server default
+
+	#[serde(default)]
+	pub totp: Option<Box<str>>, // base32 secret, asked for after the password when set
+
+	#[serde(default)]
+	pub keys: Vec<Box<str>>, // openssh public keys that can log in instead of the password
+
+	#[serde(skip)]
+	pub guest: bool, // never saved, guests only live as long as their session
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, serde::Serialize)]
+pub enum CtrlC {
+	Quit,
+	Clear, // just the input line, like a shell
+}
+
+impl std::str::FromStr for CtrlC {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"quit"  => Ok(Self::Quit),
+			"clear" => Ok(Self::Clear),
+			_ => Err(format!("expected quit or clear, got {s:?}")),
+		}
+	}
+}
+
+impl std::fmt::Display for CtrlC {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self { Self::Quit => "quit", Self::Clear => "clear" })
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, serde::Serialize)]
+pub enum Clock {
+	H12,
+	H24,
+}
+
+impl std::str::FromStr for Clock {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"12" => Ok(Self::H12),
+			"24" => Ok(Self::H24),
+			_ => Err(format!("expected 12 or 24, got {s:?}")),
+		}
+	}
+}
+
+impl std::fmt::Display for Clock {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self { Self::H12 => "12", Self::H24 => "24" })
+	}
+}
+
+impl Clock {
+	// the time of day part of a chrono format string
+	pub fn format(self, secs: bool) -> &'static str {
+		match (self, secs) {
+			(Self::H12, false) => "%I:%M %p",
+			(Self::H12, true)  => "%I:%M:%S %p",
+			(Self::H24, false) => "%H:%M",
+			(Self::H24, true)  => "%H:%M:%S",
+		}
+	}
+}
+
+// whats drawn in front of the input line, %c is the channel, %u their name, %n how many are here
+#[derive(Clone)]
+pub struct Prompt(Box<str>);
+
+impl std::str::FromStr for Prompt {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		const MAX_LEN: usize = 64;
+		if s.len() > MAX_LEN { return Err(format!("longer than {MAX_LEN} bytes")); }
+		// escapes would throw off the cursor
+		if s.chars().any(char::is_control) { return Err(String::from("no control characters")); }
+
+		let mut chars = s.chars();
+		while let Some(c) = chars.next() {
+			if c != '%' { continue; }
+			match chars.next() {
+				Some('c' | 'u' | 'n' | '%') => (),
+				Some(c) => return Err(format!("unknown placeholder %{c}")),
+				None    => return Err(String::from("trailing %, use %% for a %")),
+			}
+		}
+		Ok(Self(Box::from(s)))
+	}
+}
+
+impl std::fmt::Display for Prompt {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+		{ f.write_str(&self.0) }
+}
+
+impl Prompt {
+	// only ever holds templates from_str let through
+	pub fn render(&self, path: &std::path::Path, name: &str, here: usize) -> String {
+		let mut out = String::with_capacity(self.0.len());
+		let mut chars = self.0.chars();
+		while let Some(c) = chars.next() {
+			if c != '%' { out.push(c); continue; }
+			match chars.next() {
+				Some('c') => out += &path.display().to_string(),
+				Some('u') => out += name,
+				Some('n') => out += &here.to_string(),
+				Some(c)   => out.push(c), // %%
+				None      => (),
+			}
+		}
+		out
+	}
 }
 
 impl UserConfig {
@@ -62,6 +227,10 @@ impl UserConfig {
 		pass
 	}
 
+   fn serialize_hash<S: serde::Serializer>(hash: &u64, s: S) -> Result<S::Ok, S::Error> {
+      s.serialize_str(&format!("{hash:x}"))
+   }
+
    fn deserialize_hash<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
       use serde::de::Error;
       let s = String::deserialize(d)?;
@@ -82,127 +251,571 @@ impl UserConfig {
    pub fn get_global_perms(&self) -> PermLevel {
       self.roles.iter().fold(PermLevel::NONE, |acc, (_, p)| acc | *p)
    }
+
+   pub fn clock(&self) -> Clock
+      { self.clock.unwrap_or(CONFIG.clock) }
+
+   // what actually gets sent for what they typed
+   pub fn expand<'a>(&self, msg: &'a str) -> std::borrow::Cow<'a, str> {
+      match self.no_emoji {
+         true  => std::borrow::Cow::Borrowed(msg),
+         false => crate::emoji::expand(msg),
+      }
+   }
+
+   pub fn tz(&self) -> chrono_tz::Tz
+      { self.tz.as_deref().and_then(|tz| tz.parse().ok()).unwrap_or(chrono_tz::UTC) }
 }
 
 pub enum UserState {
    Info(Box<[u8]>),
+   // info too tall for their terminal, a page at a time
+   More { page: Box<[u8]>, rest: Box<[u8]> },
    Normal,
+   // the next line they enter goes to on_submit instead of the channel
+   Prompt { prompt: Box<str>, hidden: bool, on_submit: OnSubmit },
+   // the lines pasted so far, the one being typed is in the buffer. see commands::paste_data
+   Paste(Vec<String>),
 }
 
+pub type OnSubmit = Box<dyn FnOnce(&mut User, &[u8]) -> Result<(), CommandError> + Send>;
+
 impl User {
-   pub fn new(name: Arc<str>, config: UserConfLock, conn: Connection) -> Arc<AsyncMutex<ManuallyDrop<Self>>> {
+   pub fn new(name: Arc<str>, config: UserConfLock, conn: Arc<Connection>) -> Arc<AsyncMutex<Self>> {
+		let (path, lineage) = {
+			let server = SERVER.read();
+			match server.channel_at(&CONFIG.default_channel) {
+				Some(_) => (CONFIG.default_channel.clone(), server.lineage(&CONFIG.default_channel)),
+				// someone removed it, / is always there
+				None => {
+					warn!("default channel {} does not exist, putting {name} in /", CONFIG.default_channel.display());
+					conn.data(CryptoVec::from_slice(b"The default channel is gone, you're in / for now.\r\n"));
+					(PathBuf::from("/"), vec![Arc::clone(&server.root_channel)])
+				},
+			}
+		};
+		let channel = Channel::subscribe_as(&lineage, &name, &config.lock().unwrap());
+
 		Arc::new_cyclic(|user|
-			AsyncMutex::new(ManuallyDrop::new(Self { 
-				name, config, conn, 
-				path: PathBuf::from("/"), // TODO: save user's current channel
+			AsyncMutex::new(Self { 
+				name, config, conn, channel,
+				path, // TODO: save user's current channel
 				handle: task::spawn(Self::event_loop(user.clone())),
-				channel: crate::channel::Channel::subscribe(
-					&SERVER.read().channel_from_path(Path::new("/"))
-						.expect("default channel does not exist")),
+				stopped: false,
+				idle: CONFIG.idle_warning.map(|_| task::spawn(Self::idle_watch(user.clone()))),
+				last_active: Instant::now(),
+				idle_warned: false,
+				typing: HashMap::new(),
+				typing_sent: None,
+				status: None,
 				buffer: Vec::with_capacity(256),
 				cursor: 0,
 				state: UserState::Normal,
-			})))
+				size: None,
+				ping: None,
+			}))
    }
 
-   async fn event_loop(user: Weak<AsyncMutex<ManuallyDrop<Self>>>) {
-		let notify = match user.upgrade() {
-			Some(user) => user.lock().await.channel.notify.clone(),
-			None => panic!("User dropped on init!"),
-		};
-
+   async fn event_loop(user: Weak<AsyncMutex<Self>>) {
+      // out here so waiting on it doesnt hold the lock, along with the id of the channel its for
+      let mut rx: Option<(u64, Receiver<Event>)> = None;
       loop {
-			let mut user = match user.upgrade() {
-				Some(user) => user.lock_owned().await,
-				None => {
-					eprintln!("User dropped!");
-					break;
-				},
-			};
+         let mut locked = match user.upgrade() {
+            Some(user) => user.lock_owned().await,
+            None => {
+               debug!("user dropped, stopping event loop");
+               break;
+            },
+         };
 
-         let event = match user.channel.rx.try_recv() {
-            Err(TryRecvError::Empty) => {
-					std::mem::drop(user);
-					notify.notified().await;
-					continue;
-				},
-				// TODO we could just drop the Arc (and as a matter of fact we do)
-				// but this is here so we're certain the user burns through all of the event queue
-				// before quit. Might not be necessary given that you dont really care to get all the
-				// events if you're quitting anyway.
-            Ok(Event::Terminate) => break, 
-            Ok(event) => event,
-            Err(TryRecvError::Closed) => unreachable!(),
-            Err(TryRecvError::Lagged(num)) => {
-               #[cfg(debug_assertions)]
-               eprintln!("Event lagged");
+         // ch swaps the channel out from under us. enter always says Leave in the old one,
+         // so theres something there to wake us up and notice
+         let id = locked.channel.id;
+         let (_, rx) = match &mut rx {
+            Some(current) if current.0 == id => current,
+            rx => rx.insert((id, locked.channel.take_rx())),
+         };
 
-               user.conn.data(CryptoVec::from(format!("ECHL: Channel Lost Events: {num}\r\n"))).await;
-               continue;
+         // someone stopped typing without sending anything
+         locked.typing.retain(|_, at| at.elapsed() < TYPING_TIMEOUT);
+         if locked.status_stale() { locked.redraw(); }
+
+         let expires = locked.typing.values().min().map(|&at| at + TYPING_TIMEOUT);
+         std::mem::drop(locked);
+         let event = match expires {
+            Some(expires) => tokio::select! {
+               event = rx.recv() => event,
+               _ = tokio::time::sleep_until(expires.into()) => continue,
             },
+            None => rx.recv().await,
          };
 
-         match user.state {
-            UserState::Normal if !user.buffer.is_empty() => {
-               user.conn.data(CryptoVec::from_slice(b"\x1b[2K\r")).await;
-               user.conn.data(CryptoVec::from(format!("{event}\r\n"))).await;
-               user.conn.data(CryptoVec::from_slice(&user.buffer)).await;
+         let Some(user) = user.upgrade() else { continue; }; // caught at the top
+         let mut user = user.lock_owned().await;
+         if user.channel.id != id { continue; } // from the one they just left
+
+         let event = match event {
+            Ok(Event::Removed) | Err(RecvError::Closed) => {
+               let gone = format!("{} is gone", user.path.display());
+               user.evict(&gone);
+               continue;
             },
-            UserState::Normal => {
-               user.conn.data(CryptoVec::from(format!("{event}\r\n"))).await;
-				},
-            UserState::Info(ref data) => {
-               user.clear_info(data).await;
-               user.conn.data(CryptoVec::from(format!("{event}\r\n"))).await;
-               user.conn.data(CryptoVec::from_slice(data)).await;
-               user.conn.data(CryptoVec::from_slice(&user.buffer)).await;
+            // kicked, or whatever else took away reading
+            Ok(Event::Perms) => {
+               if !user.perms().can_read() {
+                  let kicked = format!("you cant get into {} anymore", user.path.display());
+                  user.evict(&kicked);
+               }
+               continue;
             },
-         }
+            Ok(Event::Typing(name)) => {
+               if name != user.name {
+                  user.typing.insert(name, Instant::now());
+                  if user.status_stale() { user.redraw(); }
+               }
+               continue;
+            },
+            Ok(event) => event,
+            Err(RecvError::Lagged(num)) => {
+               debug!("{} lagged behind, lost {num} events", user.name);
+
+               user.conn.data(CryptoVec::from(format!("ECHL: Channel Lost Events: {num}\r\n")));
+               continue;
+            },
+         };
+
+         // whatever they said is what they were typing
+         if let Event::Msg(_, name, _) | Event::Reply(_, name, ..) | Event::Leave(name) = &event
+            { user.typing.remove(name); }
+
+         // build it all up under the lock, send it once its let go
+         let markdown = !user.config.lock().unwrap().no_markdown;
+         let event = format!("{}\r\n", event.styled(markdown));
+         user.clear_shown();
+         let out = user.line(Some(&event));
+
+         let conn = Arc::clone(&user.conn);
+         std::mem::drop(user);
+         conn.data(out);
+      }
+   }
+
+   // their channel went away under them (or they got kicked out of it),
+   // up to the closest one still there they can read
+   fn evict(&mut self, why: &str) {
+      let (path, channel, sub) = {
+         let server = SERVER.read();
+         let config = self.config.lock().unwrap();
+         self.path.ancestors().skip(1)
+            .find_map(|path| {
+               let channel = server.channel_at(path)?;
+               let sub = Channel::subscribe_as(&server.lineage(path), &self.name, &config);
+               sub.effective_perms.can_read().then(|| (path.to_path_buf(), channel, sub))
+            })
+            .unwrap_or_else(|| {
+               let root = Arc::clone(&server.root_channel);
+               let sub = Channel::subscribe_as(&[Arc::clone(&root)], &self.name, &config);
+               (PathBuf::from("/"), root, sub)
+            })
+      };
+
+      debug!("{} lost {}, moving them to {}", self.name, self.path.display(), path.display());
+      self.clear_shown();
+      let line = self.line(Some(&format!("{why}, moving you to {}\r\n", path.display())));
+      self.conn.data(line);
+      crate::commands::enter(self, path, &channel, sub);
+   }
+
+   // no more events for this session, false if it already was
+   pub fn stop(&mut self) -> bool {
+      if self.stopped { return false; }
+      self.stopped = true;
+      self.handle.abort();
+      if let Some(idle) = &self.idle { idle.abort(); }
+      true
+   }
+
+   // what they can do in the channel they're in
+   pub fn perms(&mut self) -> PermLevel {
+      let config = self.config.lock().unwrap();
+      self.channel.perms(&self.name, &config)
+   }
+
+   // for the unread count next time they come back
+   pub fn mark_seen(&self) {
+      let last = self.channel.history.lock().unwrap().last_id();
+      self.config.lock().unwrap().last_seen.insert(self.channel.id, last);
+   }
+
+   // holds off the idle warning
+   pub fn touch(&mut self) {
+      self.last_active = Instant::now();
+      self.idle_warned = false;
+   }
+
+   // russh just drops idle connections, so give them a heads up first
+   async fn idle_watch(user: Weak<AsyncMutex<Self>>) {
+      let (Some(timeout), Some(lead)) = (CONFIG.inactivity_timeout, CONFIG.idle_warning) else { return; };
+      let warn_after = timeout.saturating_sub(lead);
+
+      loop {
+         let wake = {
+            let Some(user) = user.upgrade() else { break; };
+            let mut user = user.lock().await;
+
+            if user.last_active.elapsed() < warn_after { user.last_active + warn_after }
+            else {
+               if !user.idle_warned {
+                  user.idle_warned = true;
+                  let left = (timeout.saturating_sub(user.last_active.elapsed()).as_secs_f64().round() as u64).max(1);
+                  let msg = format!("You'll be disconnected in {} due to inactivity.\r\n",
+                     humantime::format_duration(Duration::from_secs(left)));
+                  // a line above the input like any event, info would eat the key that was meant to keep them here
+                  user.clear_shown();
+                  let line = user.line(Some(&msg));
+                  user.conn.data(line);
+               }
+               // should be gone by then, if not check back again
+               user.last_active + timeout
+            }
+         };
+
+         tokio::time::sleep_until(wake.max(Instant::now() + Duration::from_secs(1)).into()).await;
       }
    }
 
-   pub async fn clear_info(&self, data: &[u8]) {
-      match data.iter().filter(|&&b| b == b'\n').count() {
-         0 => self.conn.data(CryptoVec::from_slice(b"\x1b[2K\r")).await.unwrap(),
-         // for some reason sending the same bytes 10x in a row caused a deadlock
-         c => self.conn.data((0..c).fold(
-            CryptoVec::with_capacity(8*c),
-            |mut v, _| { v.extend(b"\x1b[1F\x1b[2K"); v }
-         )).await.unwrap(),
+   // back up over every row it took, wrapped ones too when the width is known
+   pub fn clear_info(&self, data: &[u8]) {
+      let cols = self.size.map_or(usize::MAX, |(cols, _)| cols as usize);
+      let rows = crate::wrap::rows(&String::from_utf8_lossy(data), cols);
+
+      let mut out = CryptoVec::from_slice(b"\x1b[2K\r");
+      (0..rows).for_each(|_| out.extend(b"\x1b[1F\x1b[2K"));
+      self.conn.data(out);
+   }
+
+   // whatever info or page of it is on screen
+   pub fn clear_shown(&self) {
+      match self.state {
+         UserState::Info(ref data) | UserState::More { page: ref data, .. } => self.clear_info(data),
+         _ => (),
       }
    }
 
-   pub async fn info(&mut self, data: &[u8]) {
+   pub fn info(&mut self, data: &[u8]) {
+      // wrapped here so clear_info knows how many rows it really took
+      let wrapped = self.size.map(|(cols, _)| crate::wrap::wrap(&String::from_utf8_lossy(data), cols as usize));
+      let data = wrapped.as_deref().map_or(data, str::as_bytes);
+
+      // a line is kept for --more--, unknown sizes get it all at once
+      if let Some((_, rows)) = self.size.filter(|&(_, rows)| rows > 1) {
+         let split = data.iter().enumerate()
+            .filter(|(_, &b)| b == b'\n')
+            .nth(rows as usize - 2)
+            .map(|(i, _)| i + 1)
+            .filter(|&i| i < data.len());
+         if let Some(split) = split {
+            let (page, rest) = data.split_at(split);
+            self.state = UserState::More { page: Box::from(page), rest: Box::from(rest) };
+            self.conn.data(CryptoVec::from(format!("\x1b[2K\r{}{REVERSE}--more--{RESET}", String::from_utf8_lossy(page))));
+            return;
+         }
+      }
       self.state = UserState::Info(Box::from(data));
 
       let mut msg = CryptoVec::with_capacity(5 + data.len() + 1);
       msg.extend(b"\x1b[2K\r");
       msg.extend(data);
       msg.push(b'\r');
-      self.conn.data(msg).await;
+      self.conn.data(msg);
+   }
+
+   // hidden keeps what they type off the screen, for passwords
+   pub fn prompt(&mut self, prompt: String, hidden: bool, on_submit: OnSubmit) {
+      self.buf_clear();
+      self.conn.data(CryptoVec::from(format!("\x1b[2K\r{prompt}")));
+      self.state = UserState::Prompt { prompt: Box::from(prompt), hidden, on_submit };
+   }
+
+   // the whole input line, with the cursor put back where it was
+   pub fn redraw(&mut self) {
+      if self.stopped { return; }
+      let line = self.line(None);
+      self.conn.data(line);
+   }
+
+   // the input line for whatever state they're in, with event printed above it
+   // and the typing status (if any) between the two
+   fn line(&mut self, event: Option<&str>) -> CryptoVec {
+      let status = match self.state {
+         UserState::Normal => self.typing_status(),
+         _ => None, // only gets in the way
+      };
+      let input = self.input_prompt();
+      let mut out = CryptoVec::with_capacity(16 + event.map_or(0, str::len) + input.len() + self.buffer.len());
+
+      // start over from the status line, if its there
+      if self.status.is_some() {
+         if event.is_none() && status.is_none() { out.extend(b"\x1b[2K"); } // everything moves up one
+         out.extend(b"\x1b[1F");
+      }
+      out.extend(b"\x1b[2K\r");
+      if let Some(event) = event {
+         out.extend(event.as_bytes());
+         out.extend(b"\x1b[2K");
+      }
+      if let Some(status) = &status {
+         out.extend(format!("{BRIGHT_BLACK}{status}{RESET}\r\n\x1b[2K").as_bytes());
+      }
+      self.status = status;
+
+      match self.state {
+         UserState::Normal => {
+            out.extend(input.as_bytes());
+            out.extend(&self.buffer);
+            let after = String::from_utf8_lossy(&self.buffer[self.cursor..]).chars()
+               .map(|c| c.width().unwrap_or(0)).sum::<usize>();
+            if after > 0 { out.extend(format!("\x1b[{after}D").as_bytes()); }
+         },
+         UserState::Info(ref data) => {
+            out.extend(data);
+            out.extend(input.as_bytes());
+            out.extend(&self.buffer);
+         },
+         UserState::Paste(_) => {
+            out.extend(PASTE_PROMPT.as_bytes());
+            out.extend(&self.buffer);
+         },
+         UserState::More { ref page, .. } => {
+            out.extend(page);
+            out.extend(format!("{REVERSE}--more--{RESET}").as_bytes());
+         },
+         UserState::Prompt { ref prompt, hidden, .. } => {
+            out.extend(prompt.as_bytes());
+            if !hidden { out.extend(&self.buffer); }
+         },
+      }
+      out
+   }
+
+   // who else is typing, in a way that fits on one line
+   fn typing_status(&self) -> Option<String> {
+      let mut names = self.typing.iter()
+         .filter(|(_, at)| at.elapsed() < TYPING_TIMEOUT)
+         .map(|(name, _)| &**name)
+         .collect::<Vec<_>>();
+      names.sort_unstable();
+
+      match names[..] {
+         [] => None,
+         [one] => Some(format!("{one} is typing…")),
+         [one, two] => Some(format!("{one} and {two} are typing…")),
+         _ => Some(String::from("several people are typing…")),
+      }
    }
 
+   // only redrawn in the normal state, anywhere else the cursor could be anywhere
+   fn status_stale(&self) -> bool
+      { matches!(self.state, UserState::Normal) && self.typing_status() != self.status }
+
+   // let the channel know, but not on every keystroke
+   pub fn typed(&mut self) {
+      const EVERY: Duration = Duration::from_secs(3);
+      if self.buffer.starts_with(b":") || self.typing_sent.is_some_and(|at| at.elapsed() < EVERY) { return; }
+      if !self.perms().can_write() { return; }
+
+      self.typing_sent = Some(Instant::now());
+      let _ = self.channel.send(Event::Typing(Arc::clone(&self.name)));
+   }
+
+   // their prompt filled in, a broken template gets the server default instead
+   pub fn input_prompt(&self) -> String {
+      let prompt = self.config.lock().unwrap().prompt.as_deref().and_then(|p| p.parse::<Prompt>().ok());
+      let mut prompt = prompt.as_ref().unwrap_or(&CONFIG.prompt).render(&self.path, &self.name, self.channel.here());
+      // trailing spaces get trimmed off commands, so theres always one
+      if !prompt.is_empty() { prompt.push(' '); }
+      prompt
+   }
+
+   // the char just before the cursor as bytes in the buffer and columns on screen,
+   // so backspace and the arrows never split one in half
+   pub fn char_before(&self) -> (usize, usize)
+      { char_size(self.buffer[..self.cursor].utf8_chunks().last().filter(|c| c.invalid().is_empty()).and_then(|c| c.valid().chars().next_back())) }
+
+   // same for the one under it
+   pub fn char_at(&self) -> (usize, usize)
+      { char_size(self.buffer[self.cursor..].utf8_chunks().next().and_then(|c| c.valid().chars().next())) }
+
    pub fn buf_clear(&mut self) {
       self.buffer.clear();
       self.cursor = 0;
+      self.typing_sent = None;
    }
 }
 
+// anything that isnt valid utf8 goes a byte at a time
+fn char_size(c: Option<char>) -> (usize, usize)
+   { c.map_or((1, 1), |c| (c.len_utf8(), c.width().unwrap_or(0).max(1))) }
+
 impl Drop for User {
    fn drop(&mut self) {
       self.handle.abort();
+      if let Some(idle) = &self.idle { idle.abort(); }
    }
 }
 
 
-pub struct Connection(russh::ChannelId, russh::server::Handle);
+enum Outgoing {
+   Data(CryptoVec),
+   Close,
+}
+
+// writes waiting for the client. a client that stopped reading gets cut off here instead of growing it forever
+const QUEUE: usize = 1024;
+
+// Handle::data waits for room in the session's (small, bounded) event queue, which doesnt get drained
+// while the session is stuck in a handler, say waiting on the user lock someone is holding while
+// sending to it. so nothing awaits the handle directly, everything gets queued up here instead
+// and a writer per connection feeds it to the session in order
+pub struct Connection {
+   tx:   Sender<Outgoing>,
+   kill: Arc<Notify>, // the queue filled up, stop waiting on them and close
+}
 
 impl Connection {
    pub fn new(id: russh::ChannelId, handle: russh::server::Handle) -> Self {
-      Self(id, handle)
+      Self::with_writer(move |out| {
+         let handle = handle.clone();
+         async move { match out {
+            Outgoing::Data(data) => handle.data(id, data).await.is_ok(),
+            Outgoing::Close      => { let _ = handle.close(id).await; false },
+         }}
+      })
+   }
+
+   // whatever gets it to the client, false once theyre gone
+   fn with_writer<W, F>(write: W) -> Self
+   where W: FnMut(Outgoing) -> F + Send + 'static, F: Future<Output = bool> + Send + 'static {
+      let (tx, rx) = mpsc::channel(QUEUE);
+      let kill = Arc::new(Notify::new());
+      task::spawn(Self::writer(write, rx, Arc::clone(&kill)));
+      Self { tx, kill }
+   }
+
+   async fn writer<W, F>(mut write: W, mut rx: mpsc::Receiver<Outgoing>, kill: Arc<Notify>)
+   where W: FnMut(Outgoing) -> F, F: Future<Output = bool> {
+      let drain = async {
+         while let Some(out) = rx.recv().await {
+            if !write(out).await { break; }
+         }
+      };
+      let killed = tokio::select! {
+         _ = drain           => false,
+         _ = kill.notified() => true,
+      };
+      if killed {
+         std::mem::drop(rx); // whatever was left isnt going anywhere
+         write(Outgoing::Close).await;
+      }
+   }
+
+   fn queue(&self, out: Outgoing) -> Option<()> {
+      match self.tx.try_send(out) {
+         Ok(()) => Some(()),
+         Err(TrySendError::Full(_)) => {
+            warn!("a client stopped reading and let {QUEUE} writes pile up, closing it");
+            self.kill.notify_one();
+            None
+         },
+         Err(TrySendError::Closed(_)) => None,
+      }
+   }
+
+   // None once the session is gone (or too far behind to keep)
+   pub fn data(&self, data: CryptoVec) -> Option<()> {
+      crate::metrics::sent(data.len());
+      self.queue(Outgoing::Data(data))
+   }
+
+   // goes out after whatever was sent before it
+   pub fn close(&self) -> Option<()> {
+      self.queue(Outgoing::Close)
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::time::Duration;
+
+   // everything the writer got, until it was told to close
+   async fn written(mut rx: mpsc::UnboundedReceiver<Option<Vec<u8>>>) -> Vec<Vec<u8>> {
+      let mut out = Vec::new();
+      while let Some(Some(data)) = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap() { out.push(data); }
+      out
    }
 
-   pub async fn data(&self, data: CryptoVec) -> Option<()> {
-      self.1.data(self.0, data).await.ok()
+   fn recording() -> (Connection, mpsc::UnboundedReceiver<Option<Vec<u8>>>) {
+      let (tx, rx) = mpsc::unbounded_channel();
+      let conn = Connection::with_writer(move |out| {
+         let tx = tx.clone();
+         async move { match out {
+            Outgoing::Data(data) => tx.send(Some(data.to_vec())).is_ok(),
+            Outgoing::Close      => { let _ = tx.send(None); false },
+         }}
+      });
+      (conn, rx)
+   }
+
+   // clear_info used to need one big write for this
+   #[tokio::test]
+   async fn same_bytes_over_and_over() {
+      let (conn, rx) = recording();
+      for _ in 0..QUEUE - 1 { conn.data(CryptoVec::from_slice(b"\x1b[2K\r")).unwrap(); }
+      conn.close().unwrap();
+
+      let out = written(rx).await;
+      assert_eq!(out.len(), QUEUE - 1);
+      assert!(out.iter().all(|data| data == b"\x1b[2K\r"));
+   }
+
+   #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+   async fn many_writers_in_order_each() {
+      let (conn, rx) = recording();
+      let conn = Arc::new(conn);
+      let tasks = (0..8u8).map(|n| {
+         let conn = Arc::clone(&conn);
+         tokio::spawn(async move {
+            for i in 0..100u8 {
+               conn.data(CryptoVec::from_slice(&[n, i])).unwrap();
+            }
+         })
+      }).collect::<Vec<_>>();
+      for task in tasks { task.await.unwrap(); }
+      conn.close().unwrap();
+
+      let out = written(rx).await;
+      assert_eq!(out.len(), 800);
+      for n in 0..8u8 {
+         let seq = out.iter().filter(|data| data[0] == n).map(|data| data[1]).collect::<Vec<_>>();
+         assert_eq!(seq, (0..100).collect::<Vec<_>>());
+      }
+   }
+
+   // a client that never reads gets closed instead of queued up for forever
+   #[tokio::test]
+   async fn stuck_client_gets_closed() {
+      let (tx, mut rx) = mpsc::unbounded_channel();
+      let conn = Connection::with_writer(move |out| {
+         let tx = tx.clone();
+         async move { match out {
+            Outgoing::Data(_) => std::future::pending().await,
+            Outgoing::Close   => { let _ = tx.send(()); false },
+         }}
+      });
+
+      let sent = (0..QUEUE * 2).take_while(|_| conn.data(CryptoVec::from_slice(b"hi")).is_some()).count();
+      assert!(sent <= QUEUE + 1);
+      tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
    }
 }
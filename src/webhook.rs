@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use log::{info, warn, debug};
+
+use crate::channel::Channel;
+use crate::event::Event;
+use crate::SERVER;
+
+#[derive(Clone)]
+pub struct Webhooks {
+	pub urls:     Vec<String>,
+	pub channels: Vec<PathBuf>,
+	pub events:   Vec<EventKind>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EventKind { Msg, Reply, Edit, Delete, Join, Leave }
+
+impl FromStr for EventKind {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"msg"   => Ok(Self::Msg),
+			"reply" => Ok(Self::Reply),
+			"edit"  => Ok(Self::Edit),
+			"delete" => Ok(Self::Delete),
+			"join"  => Ok(Self::Join),
+			"leave" => Ok(Self::Leave),
+			_ => Err(format!("unknown event {s:?}, expected msg, reply, edit, delete, join or leave")),
+		}
+	}
+}
+
+impl EventKind {
+	fn of(event: &Event) -> Option<Self> {
+		Some(match event {
+			Event::Msg(..)   => Self::Msg,
+			Event::Reply(..) => Self::Reply,
+			Event::Edit(..)  => Self::Edit,
+			Event::Delete(_) => Self::Delete,
+			Event::Join(_)   => Self::Join,
+			Event::Leave(_)  => Self::Leave,
+			Event::Typing(_) | Event::Perms | Event::Removed => return None,
+		})
+	}
+}
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| reqwest::Client::builder()
+	.timeout(Duration::from_secs(10))
+	.user_agent(concat!("crussh/", env!("CARGO_PKG_VERSION")))
+	.build()
+	.unwrap_or_else(|e| panic!("Error building webhook client: {e}")));
+
+// one listener per watched channel, each event gets posted to every url
+pub fn start(hooks: &Webhooks) {
+	let urls = Arc::<[String]>::from(hooks.urls.as_slice());
+	let events = Arc::<[EventKind]>::from(hooks.events.as_slice());
+
+	for path in &hooks.channels {
+		let Some(channel) = SERVER.read().channel_from_path(path)
+			else { warn!("no channel at {}, not watching it for webhooks", path.display()); continue; };
+
+		info!("firing webhooks for {}", path.display());
+		tokio::spawn(watch(Channel::subscribe(&channel), path.clone(), Arc::clone(&urls), Arc::clone(&events)));
+	}
+}
+
+async fn watch(
+	mut channel: crate::channel::SubscribedChannel,
+	path: PathBuf,
+	urls: Arc<[String]>,
+	events: Arc<[EventKind]>) {
+	loop {
+		let event = match channel.rx.recv().await {
+			Ok(event) => event,
+			Err(RecvError::Lagged(count)) => { warn!("webhooks for {} missed {count} events", path.display()); continue; },
+			Err(RecvError::Closed) => break,
+		};
+
+		if matches!(event, Event::Removed) { break; }
+		if !EventKind::of(&event).is_some_and(|kind| events.contains(&kind)) { continue; }
+		let mut body = event.to_json();
+		body["channel"] = path.display().to_string().into();
+		let body = Arc::<str>::from(body.to_string());
+
+		// deliveries can take a while with retries, dont hold up the channel for them
+		for url in urls.iter() { tokio::spawn(post(url.clone(), Arc::clone(&body))); }
+	}
+
+	warn!("{} is gone, no more webhooks for it", path.display());
+}
+
+async fn post(url: String, body: Arc<str>) {
+	const ATTEMPTS: u32 = 4;
+
+	let mut backoff = Duration::from_secs(1);
+	for attempt in 1..=ATTEMPTS {
+		let res = CLIENT.post(&url)
+			.header(reqwest::header::CONTENT_TYPE, "application/json")
+			.body(body.to_string())
+			.send().await
+			.and_then(reqwest::Response::error_for_status);
+
+		match res {
+			Ok(_) => return debug!("webhook {url} delivered"),
+			// 4xx wont get any better by asking again
+			Err(e) if e.status().is_some_and(|s| s.is_client_error()) => return warn!("webhook {url} rejected: {e}"),
+			Err(e) if attempt == ATTEMPTS => warn!("webhook {url} failed, giving up: {e}"),
+			Err(e) => {
+				debug!("webhook {url} failed (attempt {attempt}), retrying in {}s: {e}", backoff.as_secs());
+				tokio::time::sleep(backoff).await;
+				backoff *= 2;
+			},
+		}
+	}
+}
@@ -0,0 +1,146 @@
+// breaking lines before the terminal does, so whats on screen is known down to the row.
+// escapes take no room, and \r or \n start the count over
+use unicode_width::UnicodeWidthChar;
+
+// at the last space that fits where theres one, mid word where there isnt
+pub fn wrap(text: &str, cols: usize) -> String {
+	let mut out = String::with_capacity(text.len() + 16);
+	let mut col = 0;
+	let mut space = None; // where in out the last space on this line is, and the column after it
+	let mut broke = false; // spaces right after a break would just indent the next line
+
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'\x1b' => {
+				out.push(c);
+				escape(&mut chars, |c| out.push(c));
+				continue;
+			},
+			'\r' | '\n' => {
+				(col, space, broke) = (0, None, false);
+				out.push(c);
+				continue;
+			},
+			' ' if broke => continue,
+			_ => broke = false,
+		}
+
+		let width = c.width().unwrap_or(0);
+		if col + width > cols {
+			// the space itself is as good a place as any
+			if c == ' ' {
+				out += "\r\n";
+				(col, space, broke) = (0, None, true);
+				continue;
+			}
+			if let Some((i, after)) = space.take() {
+				out.replace_range(i..i + 1, "\r\n");
+				col -= after;
+			}
+			if col + width > cols {
+				out += "\r\n";
+				col = 0;
+			}
+		}
+
+		if c == ' ' { space = Some((out.len(), col + 1)); }
+		out.push(c);
+		col += width;
+	}
+	out
+}
+
+// how many rows down the cursor ends up after text is printed from the start of one.
+// \n is the only thing that moves down by itself, \r just goes back to the start,
+// and anything that doesnt fit wraps like the terminal would
+pub fn rows(text: &str, cols: usize) -> usize {
+	let (mut rows, mut col) = (0, 0);
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'\x1b' => escape(&mut chars, |_| ()),
+			'\r' => col = 0,
+			'\n' => rows += 1,
+			c => {
+				let width = c.width().unwrap_or(0);
+				if col + width > cols { (rows, col) = (rows + 1, 0); }
+				col += width;
+			},
+		}
+	}
+	rows
+}
+
+// the rest of an escape after the ESC, csi up to its final byte and osc up to ST or BEL
+fn escape(chars: &mut std::iter::Peekable<std::str::Chars>, mut out: impl FnMut(char)) {
+	match chars.next_if(|&c| c == '[' || c == ']') {
+		Some('[') => {
+			out('[');
+			for c in chars.by_ref() {
+				out(c);
+				if ('@'..='~').contains(&c) { break; }
+			}
+		},
+		Some(_) => {
+			out(']');
+			while let Some(c) = chars.next() {
+				out(c);
+				if c == '\x07' { break; }
+				if c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+					out('\\');
+					break;
+				}
+			}
+		},
+		// two byte ones like ESC \
+		None => chars.next().into_iter().for_each(out),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn embedded_newlines() {
+		assert_eq!(wrap("ab\r\ncd", 3), "ab\r\ncd");
+		assert_eq!(wrap("aaa bbb\r\ncc dd", 5), "aaa\r\nbbb\r\ncc dd");
+		// a bare \n starts the count over too
+		assert_eq!(wrap("hello world\nfoo", 5), "hello\r\nworld\nfoo");
+		assert_eq!(rows("hello\r\nworld\r\nfoo", 5), 2);
+		// but on screen it moves down without going back, so the rest carries on from there
+		assert_eq!(rows("abc\ndef", 5), 2);
+		assert_eq!(rows("abc\rdef", 10), 0);
+		assert_eq!(rows("abc\n", 10), 1);
+	}
+
+	#[test]
+	fn words_longer_than_the_width() {
+		assert_eq!(wrap("abcdefgh", 3), "abc\r\ndef\r\ngh");
+		assert_eq!(wrap("hi abcdefgh", 4), "hi\r\nabcd\r\nefgh");
+		assert_eq!(rows("abcdefgh", 3), 2);
+		// no leading spaces after a break
+		assert_eq!(wrap("abc   def", 3), "abc\r\ndef");
+	}
+
+	#[test]
+	fn wide_chars() {
+		assert_eq!(wrap("日本語", 4), "日本\r\n語");
+		assert_eq!(wrap("a日本", 4), "a日\r\n本");
+		assert_eq!(rows("a日本", 4), 1);
+		// exactly full doesnt wrap until something comes after
+		assert_eq!(rows("日本", 4), 0);
+	}
+
+	#[test]
+	fn escapes_take_no_room() {
+		assert_eq!(wrap("\x1b[1mbold\x1b[0m text", 4), "\x1b[1mbold\x1b[0m\r\ntext");
+		assert_eq!(rows(&wrap("\x1b[1mbold\x1b[0m text", 4), 4), 1);
+
+		let link = "\x1b]8;;http://x\x1b\\link\x1b]8;;\x1b\\";
+		assert_eq!(wrap(link, 4), link);
+		assert_eq!(rows(link, 4), 0);
+		assert_eq!(rows("\x1b]0;title\x07abcd", 4), 0);
+	}
+}
@@ -0,0 +1,158 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::Message;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use log::{info, warn, debug};
+
+use crate::channel::Channel;
+use crate::event::{Event, no_controls};
+use crate::user::UserConfig;
+use crate::{SERVER, CONFIG};
+
+// websocket gateway into the same channels ssh users see.
+// first frame is `{"user": .., "pass": .., "channel": .., "channel_pass": ..}`, after that every text frame 
+// is a message and every event comes back as a json object.
+// no commands. ws sessions count as online and towards MAX_CONNECTIONS and MAX_SESSIONS like ssh ones
+pub async fn serve(addr: SocketAddr) {
+	let listener = match TcpListener::bind(addr).await {
+		Ok(listener) => listener,
+		Err(e) => return warn!("Error listening for websockets on {addr}: {e}"),
+	};
+	info!("serving websockets on {addr}");
+
+	loop {
+		match listener.accept().await {
+			Ok((stream, addr)) => { tokio::spawn(session(stream, addr)); },
+			Err(e) => debug!("Error accepting websocket connection: {e}"),
+		}
+	}
+}
+
+#[derive(serde::Deserialize)]
+struct Login {
+	user:    String,
+	pass:    String,
+	channel: Option<String>,
+	channel_pass: Option<String>, // for channels with a password, same as ch asks for
+}
+
+// keeps a session counted for as long as its around, whichever way it ends
+struct Counted(Option<Arc<str>>);
+
+impl Counted {
+	fn new() -> Self {
+		crate::metrics::CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+		Self(None)
+	}
+}
+
+impl Drop for Counted {
+	fn drop(&mut self) {
+		crate::metrics::CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+		if let Some(name) = &self.0 { SERVER.ws_offline(name); }
+	}
+}
+
+async fn session(stream: TcpStream, addr: SocketAddr) {
+	let mut counted = Counted::new();
+	let mut ws = match tokio_tungstenite::accept_async(stream).await {
+		Ok(ws) => ws,
+		Err(e) => return debug!("websocket handshake with {addr} failed: {e}"),
+	};
+
+	macro_rules! error {
+		($msg:expr) => {{
+			let _ = ws.send(Message::text(json!({ "type": "error", "error": $msg }).to_string())).await;
+			let _ = ws.close(None).await;
+			return;
+		}}
+	}
+
+	let login = match ws.next().await {
+		Some(Ok(Message::Text(login))) => login,
+		_ => error!("expected a login"),
+	};
+	let Ok(login) = serde_json::from_str::<Login>(&login) else { error!("malformed login") };
+
+	let Login { user, pass, channel, channel_pass } = login;
+
+	// this one counts too, so its over and not at
+	let connections = crate::metrics::CONNECTIONS.load(Ordering::Relaxed) as usize;
+	if CONFIG.max_connections.is_some_and(|max| connections > max) {
+		warn!("turning {user} away over websocket, {connections} connections is over MAX_CONNECTIONS");
+		error!("too many connections")
+	}
+	let valid = tokio::task::spawn_blocking(move || SERVER.read().validate_pass(&user, &pass).map(|config| (user, config)))
+		.await.unwrap_or(None);
+	let Some((name, config)) = valid.map(|(name, config)| (Arc::<str>::from(name), config)) else {
+		warn!("failed websocket login from {addr}");
+		crate::metrics::inc(&crate::metrics::AUTH_FAILURES);
+		error!("invalid login")
+	};
+	// theres nowhere to ask for the code over here
+	#[cfg(feature = "totp")]
+	if CONFIG.totp && config.lock().unwrap().totp.is_some() { error!("accounts with 2fa cant log in over websocket") }
+
+	if !SERVER.ws_online(&name, CONFIG.max_sessions) {
+		warn!("{name} already has {} sessions open", CONFIG.max_sessions.unwrap_or_default());
+		error!("too many sessions open")
+	}
+	counted.0 = Some(Arc::clone(&name));
+
+	let path = channel.unwrap_or_else(|| String::from("/"));
+	let lineage = SERVER.read().lineage(Path::new(&path));
+	let mut channel = Channel::subscribe_as(&lineage, &name, &config.lock().unwrap());
+	if !channel.effective_perms.can_read() { error!("forbidden") }
+
+	// managers dont need the password to their own channel, same as ch
+	let password = lineage.last().and_then(|c| c.read().unwrap().password);
+	if password.is_some() && !channel.effective_perms.can_manage()
+		&& channel_pass.map(|pass| UserConfig::hash(pass.as_bytes())) != password {
+		warn!("{name} got the password for {path} wrong over websocket");
+		error!("wrong channel password")
+	}
+
+	info!("{name} connected over websocket from {addr}");
+	let _ = channel.send(Event::Join(Arc::clone(&name)));
+
+	loop {
+		tokio::select! {
+			event = channel.rx.recv() => match event {
+				// theres nowhere to move them to like over ssh, so thats the end of it
+				Ok(Event::Perms) => if !channel.perms(&name, &config.lock().unwrap()).can_read() {
+					let _ = ws.send(Message::text(json!({ "type": "error", "error": "forbidden" }).to_string())).await;
+					break;
+				},
+				Ok(event) => {
+					if ws.send(Message::text(event.to_json().to_string())).await.is_err() { break; }
+					if matches!(event, Event::Removed) { break; } // nothing left to talk to
+				},
+				Err(RecvError::Lagged(count)) => {
+					let lagged = json!({ "type": "lagged", "count": count }).to_string();
+					if ws.send(Message::text(lagged)).await.is_err() { break; }
+				},
+				Err(RecvError::Closed) => break,
+			},
+			msg = ws.next() => match msg {
+				Some(Ok(Message::Text(msg))) => {
+					const MAX_MSG_LEN: usize = 1024;
+					if msg.is_empty() || msg.len() > MAX_MSG_LEN { continue; }
+					if !channel.perms(&name, &config.lock().unwrap()).can_write() { continue; }
+					let msg = no_controls(&msg);
+					if msg.is_empty() { continue; }
+					let _ = channel.send(Event::msg(Arc::clone(&name), msg));
+				},
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+				Some(Ok(_)) => (),
+			},
+		}
+	}
+
+	info!("{name} disconnected from websocket");
+	let _ = channel.send(Event::Leave(name));
+}